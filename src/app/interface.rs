@@ -1,17 +1,30 @@
+use std::time::Instant;
+
+use tuirealm::props::PropPayload;
 use tuirealm::terminal::TerminalBridge;
-use tuirealm::tui::layout::{Constraint, Direction, Layout};
+use tuirealm::tui::layout::{Constraint, Direction, Layout, Rect};
+use tuirealm::tui::widgets::Clear;
+use tuirealm::tui::Frame;
 use tuirealm::{Application, AttrValue, Attribute, Sub, SubClause, SubEventClause};
+use uuid::Uuid;
+
+use crate::app::notifications::Severity;
+use crate::app::tasks::{ActionInfo, TaskInfo, TaskStatus};
 
 use crate::components::container::{Header, Preview};
 use crate::components::input::TextInput;
 use crate::components::label::TextLabel;
+use crate::components::modal::{DeleteModal, PromptModal};
 use crate::components::paragraph::ServerListDisconnected;
+use crate::components::paragraph::{BatchProgress, DebugLog, ErrorPane, Toasts, UserDataPreview};
 use crate::components::phantom::PhantomHandler;
-use crate::components::table::ServerListConnected;
+use crate::components::picker::Picker;
+use crate::components::table::{ActionLog, NotificationPanel, ServerListConnected, TaskPanel};
 use crate::constants::{
-    Components, Id, InputId, InterfaceMsg, Msg, ProviderStatus, ServerHandle, ServerListStatus,
-    UserEvent, UserEventIter,
+    Components, Id, InputId, InterfaceMsg, Msg, ServerHandle, ServerListStatus, UserEvent,
+    UserEventIter,
 };
+use crate::providers::{Action, ActionStatus};
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub enum Interface {
@@ -26,7 +39,7 @@ impl Interface {
             Components::Header(_) => vec![
                 Sub::new(
                     SubEventClause::User(UserEventIter::new(vec![UserEvent::ProviderStatus(
-                        ProviderStatus::default(),
+                        Vec::new(),
                     )])),
                     SubClause::Always,
                 ),
@@ -34,19 +47,97 @@ impl Interface {
                     SubEventClause::User(UserEventIter::new(vec![UserEvent::Refresh])),
                     SubClause::Always,
                 ),
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![UserEvent::Polled(
+                        Vec::new(),
+                        None,
+                    )])),
+                    SubClause::Always,
+                ),
             ],
-            Components::ServerListConnected(_) => {
-                vec![Sub::new(
+            Components::ServerListConnected(_) => vec![
+                Sub::new(
                     SubEventClause::User(UserEventIter::new(vec![UserEvent::ServerListStatus(
                         ServerListStatus::default(),
                     )])),
                     SubClause::Always,
-                )]
-            }
-            Components::TextLabel(_) => vec![Sub::new(
-                SubEventClause::User(UserEventIter::new(vec![UserEvent::Error(String::new())])),
+                ),
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![UserEvent::Polled(
+                        Vec::new(),
+                        None,
+                    )])),
+                    SubClause::Always,
+                ),
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![UserEvent::ServerPage(
+                        ServerListStatus::default(),
+                    )])),
+                    SubClause::Always,
+                ),
+            ],
+            Components::Picker(_) => vec![Sub::new(
+                SubEventClause::User(UserEventIter::new(vec![UserEvent::CreateOptions(
+                    Box::default(),
+                )])),
+                SubClause::Always,
+            )],
+            Components::BatchProgress(_) => vec![Sub::new(
+                SubEventClause::User(UserEventIter::new(vec![UserEvent::BatchProgress(
+                    String::new(),
+                    None,
+                )])),
+                SubClause::Always,
+            )],
+            Components::TaskPanel(_) => vec![
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![UserEvent::TaskProgress(
+                        TaskInfo {
+                            id: Uuid::nil(),
+                            name: String::new(),
+                            status: TaskStatus::Running,
+                            progress: None,
+                            started: Instant::now(),
+                            finished: None,
+                        },
+                    )])),
+                    SubClause::Always,
+                ),
+                Sub::new(SubEventClause::Tick, SubClause::Always),
+            ],
+            Components::ActionLog(_) => vec![Sub::new(
+                SubEventClause::User(UserEventIter::new(vec![UserEvent::ActionProgress(
+                    ActionInfo {
+                        account: String::new(),
+                        subject: String::new(),
+                        action: Action {
+                            id: String::new(),
+                            command: String::new(),
+                            progress: 0,
+                            status: ActionStatus::Running,
+                        },
+                    },
+                )])),
                 SubClause::Always,
             )],
+            Components::TextLabel(_) => vec![
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![
+                        UserEvent::Error(Box::default()),
+                    ])),
+                    SubClause::Always,
+                ),
+                Sub::new(
+                    SubEventClause::User(UserEventIter::new(vec![UserEvent::Notice(
+                        Severity::Info,
+                        String::new(),
+                    )])),
+                    SubClause::Always,
+                ),
+            ],
+            Components::Toasts(_) | Components::DebugLog(_) => {
+                vec![Sub::new(SubEventClause::Tick, SubClause::Always)]
+            }
             _ => Vec::default(),
         };
         assert!(app.mount(id.clone(), component.unwrap(), subs).is_ok());
@@ -75,6 +166,11 @@ impl Interface {
                     Id::Preview,
                     Components::ServerPreview(Preview::default()),
                 );
+                self.mount(
+                    app,
+                    Id::ActionLog,
+                    Components::ActionLog(ActionLog::new(Vec::new())),
+                );
                 self.mount(app, Id::Label, Components::TextLabel(TextLabel::default()));
 
                 // Activate header
@@ -103,17 +199,92 @@ impl Interface {
                 self.mount(
                     app,
                     Id::CreateServer1,
-                    Components::TextInput(TextInput::new(InputId::CreateServerName, " Name ")),
+                    Components::TextInput(TextInput::new(
+                        InputId::CreateServerName,
+                        " Name (Enter moves to the next field, Enter on User data creates) ",
+                    )),
+                );
+                self.mount(
+                    app,
+                    Id::Blueprint,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerBlueprint,
+                        " Blueprint (Up/Down) ",
+                    ))),
                 );
                 self.mount(
                     app,
                     Id::CreateServer2,
-                    Components::TextInput(TextInput::new(InputId::CreateServerType, " Type ")),
+                    Components::Picker(Box::new(Picker::new(InputId::CreateServerType, " Type "))),
                 );
                 self.mount(
                     app,
                     Id::CreateServer3,
-                    Components::TextInput(TextInput::new(InputId::CreateServerImage, " Image ")),
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerImage,
+                        " Image ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer4,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerLocation,
+                        " Location ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer5,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerDatacenter,
+                        " Datacenter ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer6,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerSshKeys,
+                        " SSH keys (Space) ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer7,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerPublicNet,
+                        " Public IPs (Space) ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer8,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerNetworks,
+                        " Networks (Space) ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer9,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerFirewalls,
+                        " Firewalls (Space) ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::CreateServer10,
+                    Components::Picker(Box::new(Picker::new(
+                        InputId::CreateServerUserData,
+                        " User data ",
+                    ))),
+                );
+                self.mount(
+                    app,
+                    Id::UserDataPreview,
+                    Components::UserDataPreview(UserDataPreview::default()),
                 );
 
                 self.mount(app, Id::Label, Components::TextLabel(TextLabel::default()));
@@ -156,8 +327,35 @@ impl Interface {
                             .split(f.size());
                         app.view(&Id::Header, f, chunks[0]);
                         app.view(&Id::ServerList, f, chunks[1]);
-                        app.view(&Id::Preview, f, chunks[2]);
+                        let ui = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .margin(0)
+                            .constraints(
+                                [Constraint::Percentage(50), Constraint::Percentage(50)].as_ref(),
+                            )
+                            .split(chunks[2]);
+                        app.view(&Id::Preview, f, ui[0]);
+                        if app.mounted(&Id::BatchProgress) {
+                            let progress = Layout::default()
+                                .direction(Direction::Vertical)
+                                .margin(0)
+                                .constraints(
+                                    [Constraint::Percentage(50), Constraint::Percentage(50)]
+                                        .as_ref(),
+                                )
+                                .split(ui[1]);
+                            app.view(&Id::BatchProgress, f, progress[0]);
+                            app.view(&Id::ActionLog, f, progress[1]);
+                        } else {
+                            app.view(&Id::ActionLog, f, ui[1]);
+                        }
                         app.view(&Id::Label, f, chunks[3]);
+                        Self::view_toasts(app, f, chunks[3]);
+                        Self::view_modal(app, f);
+                        Self::view_tasks(app, f);
+                        Self::view_notifications(app, f);
+                        Self::view_debug(app, f);
+                        Self::view_error(app, f);
                     })
                     .is_ok());
             }
@@ -170,10 +368,10 @@ impl Interface {
                             .margin(0)
                             .constraints(
                                 [
-                                    Constraint::Length(8),  // Header
-                                    Constraint::Length(12), // List
-                                    Constraint::Fill(1),    // UI
-                                    Constraint::Length(3),  // Label
+                                    Constraint::Length(8), // Header
+                                    Constraint::Length(8), // List
+                                    Constraint::Fill(1),   // UI
+                                    Constraint::Length(3), // Label
                                 ]
                                 .as_ref(),
                             )
@@ -187,24 +385,188 @@ impl Interface {
                             .constraints(
                                 [
                                     Constraint::Length(3),
-                                    Constraint::Length(3),
-                                    Constraint::Length(3),
+                                    Constraint::Fill(1),
+                                    Constraint::Fill(1),
+                                    Constraint::Fill(1),
                                 ]
                                 .as_ref(),
                             )
                             .split(chunks[2]);
+                        let name = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .margin(0)
+                            .constraints(
+                                [Constraint::Percentage(75), Constraint::Percentage(25)].as_ref(),
+                            )
+                            .split(sub_chunks[0]);
+                        let placement = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .margin(0)
+                            .constraints(
+                                [
+                                    Constraint::Percentage(40),
+                                    Constraint::Percentage(20),
+                                    Constraint::Percentage(20),
+                                    Constraint::Percentage(20),
+                                ]
+                                .as_ref(),
+                            )
+                            .split(sub_chunks[1]);
+                        let access = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .margin(0)
+                            .constraints([Constraint::Percentage(25); 4].as_ref())
+                            .split(sub_chunks[2]);
+                        let user_data = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .margin(0)
+                            .constraints(
+                                [Constraint::Percentage(25), Constraint::Percentage(75)].as_ref(),
+                            )
+                            .split(sub_chunks[3]);
 
-                        app.view(&Id::CreateServer1, f, sub_chunks[0]);
-                        app.view(&Id::CreateServer2, f, sub_chunks[1]);
-                        app.view(&Id::CreateServer3, f, sub_chunks[2]);
+                        app.view(&Id::CreateServer1, f, name[0]);
+                        app.view(&Id::Blueprint, f, name[1]);
+                        app.view(&Id::CreateServer2, f, placement[0]);
+                        app.view(&Id::CreateServer3, f, placement[1]);
+                        app.view(&Id::CreateServer4, f, placement[2]);
+                        app.view(&Id::CreateServer5, f, placement[3]);
+                        app.view(&Id::CreateServer6, f, access[0]);
+                        app.view(&Id::CreateServer7, f, access[1]);
+                        app.view(&Id::CreateServer8, f, access[2]);
+                        app.view(&Id::CreateServer9, f, access[3]);
+                        app.view(&Id::CreateServer10, f, user_data[0]);
+                        app.view(&Id::UserDataPreview, f, user_data[1]);
 
                         app.view(&Id::Label, f, chunks[3]);
+                        Self::view_toasts(app, f, chunks[3]);
+                        Self::view_modal(app, f);
+                        Self::view_tasks(app, f);
+                        Self::view_notifications(app, f);
+                        Self::view_debug(app, f);
+                        Self::view_error(app, f);
                     })
                     .is_ok())
             }
         }
     }
 
+    /// Draws the toasts in the bottom right corner, just above the label.
+    fn view_toasts(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame, label: Rect) {
+        let lines = match app.query(&Id::Toasts, Attribute::Text) {
+            Ok(Some(AttrValue::Payload(PropPayload::Vec(lines)))) => lines.len() as u16,
+            _ => return,
+        };
+
+        let width = label.width.min(100);
+        let height = (lines + 2).min(label.y);
+        let popup = Rect::new(
+            label.x + label.width - width,
+            label.y - height,
+            width,
+            height,
+        );
+        f.render_widget(Clear, popup);
+        app.view(&Id::Toasts, f, popup);
+    }
+
+    /// Draws the open modal, if any, centered over the current screen.
+    fn view_modal(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame) {
+        let Some(id) = [Id::DeleteModal, Id::Prompt]
+            .into_iter()
+            .find(|id| app.mounted(id))
+        else {
+            return;
+        };
+
+        let area = f.size();
+        let width = area.width.min(88);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + area.height.saturating_sub(3) / 2,
+            width,
+            area.height.min(3),
+        );
+        f.render_widget(Clear, popup);
+        app.view(&id, f, popup);
+    }
+
+    /// Draws the task panel over everything else, modals included.
+    fn view_tasks(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame) {
+        if !app.mounted(&Id::TaskPanel) {
+            return;
+        }
+
+        let area = f.size();
+        let width = area.width.min(120);
+        let height = area.height.min(20);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        f.render_widget(Clear, popup);
+        app.view(&Id::TaskPanel, f, popup);
+    }
+
+    /// Draws the notification history over the task panel.
+    fn view_notifications(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame) {
+        if !app.mounted(&Id::NotificationPanel) {
+            return;
+        }
+
+        let area = f.size();
+        let width = area.width.min(120);
+        let height = area.height.min(24);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        f.render_widget(Clear, popup);
+        app.view(&Id::NotificationPanel, f, popup);
+    }
+
+    /// Draws the debug log over most of the screen.
+    fn view_debug(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame) {
+        if !app.mounted(&Id::DebugLog) {
+            return;
+        }
+
+        let area = f.size();
+        let width = area.width.min(160);
+        let height = area.height.saturating_sub(4);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        f.render_widget(Clear, popup);
+        app.view(&Id::DebugLog, f, popup);
+    }
+
+    /// Draws the error pane over everything else, the task panel included.
+    fn view_error(app: &mut Application<Id, Msg, UserEventIter>, f: &mut Frame) {
+        if !app.mounted(&Id::ErrorPane) {
+            return;
+        }
+
+        let area = f.size();
+        let width = area.width.min(100);
+        let height = area.height.min(16);
+        let popup = Rect::new(
+            area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2,
+            width,
+            height,
+        );
+        f.render_widget(Clear, popup);
+        app.view(&Id::ErrorPane, f, popup);
+    }
+
     pub fn change_focus(&self, app: &mut Application<Id, Msg, UserEventIter>) -> Option<Msg> {
         match self {
             Interface::Status => {
@@ -219,6 +581,10 @@ impl Interface {
                             None
                         }
                         Id::Preview => {
+                            assert!(app.active(&Id::ActionLog).is_ok());
+                            None
+                        }
+                        Id::ActionLog => {
                             assert!(app.active(&Id::Header).is_ok());
                             None
                         }
@@ -243,6 +609,10 @@ impl Interface {
                             None
                         }
                         Id::CreateServer1 => {
+                            assert!(app.active(&Id::Blueprint).is_ok());
+                            None
+                        }
+                        Id::Blueprint => {
                             assert!(app.active(&Id::CreateServer2).is_ok());
                             None
                         }
@@ -251,6 +621,34 @@ impl Interface {
                             None
                         }
                         Id::CreateServer3 => {
+                            assert!(app.active(&Id::CreateServer4).is_ok());
+                            None
+                        }
+                        Id::CreateServer4 => {
+                            assert!(app.active(&Id::CreateServer5).is_ok());
+                            None
+                        }
+                        Id::CreateServer5 => {
+                            assert!(app.active(&Id::CreateServer6).is_ok());
+                            None
+                        }
+                        Id::CreateServer6 => {
+                            assert!(app.active(&Id::CreateServer7).is_ok());
+                            None
+                        }
+                        Id::CreateServer7 => {
+                            assert!(app.active(&Id::CreateServer8).is_ok());
+                            None
+                        }
+                        Id::CreateServer8 => {
+                            assert!(app.active(&Id::CreateServer9).is_ok());
+                            None
+                        }
+                        Id::CreateServer9 => {
+                            assert!(app.active(&Id::CreateServer10).is_ok());
+                            None
+                        }
+                        Id::CreateServer10 => {
                             assert!(app.active(&Id::Header).is_ok());
                             None
                         }
@@ -273,6 +671,10 @@ impl Interface {
     ) -> Option<Msg> {
         match msg {
             InterfaceMsg::Connected => {
+                // A list already showing servers keeps its selection and marks
+                if let Ok(Some(_)) = app.query(&Id::ServerList, Attribute::Custom("names")) {
+                    return Some(Msg::FetchServers);
+                }
                 if app.mounted(&Id::ServerList) {
                     assert!(app.umount(&Id::ServerList).is_ok());
                 }
@@ -314,6 +716,156 @@ impl Interface {
                     }
                 }
 
+                None
+            }
+            InterfaceMsg::PreviewUserData(content) => {
+                if app.mounted(&Id::UserDataPreview) {
+                    assert!(app.umount(&Id::UserDataPreview).is_ok());
+                }
+
+                self.mount(
+                    app,
+                    Id::UserDataPreview,
+                    Components::UserDataPreview(UserDataPreview::new(&content)),
+                );
+
+                None
+            }
+            InterfaceMsg::OpenDeleteModal(confirm, subject) => {
+                self.mount(
+                    app,
+                    Id::DeleteModal,
+                    Components::DeleteModal(DeleteModal::new(&confirm, &subject)),
+                );
+                assert!(app.active(&Id::DeleteModal).is_ok());
+
+                None
+            }
+            InterfaceMsg::OpenPrompt(prompt) => {
+                self.mount(
+                    app,
+                    Id::Prompt,
+                    Components::PromptModal(PromptModal::new(prompt)),
+                );
+                assert!(app.active(&Id::Prompt).is_ok());
+
+                None
+            }
+            InterfaceMsg::ShowBatch(title, servers) => {
+                if app.mounted(&Id::BatchProgress) {
+                    assert!(app.umount(&Id::BatchProgress).is_ok());
+                }
+                self.mount(
+                    app,
+                    Id::BatchProgress,
+                    Components::BatchProgress(BatchProgress::new(&title, servers)),
+                );
+
+                None
+            }
+            InterfaceMsg::ShowActions(actions) => {
+                if !app.mounted(&Id::ActionLog) {
+                    return None;
+                }
+                assert!(app.umount(&Id::ActionLog).is_ok());
+                self.mount(
+                    app,
+                    Id::ActionLog,
+                    Components::ActionLog(ActionLog::new(actions)),
+                );
+
+                None
+            }
+            InterfaceMsg::CloseBatch => {
+                if app.mounted(&Id::BatchProgress) {
+                    assert!(app.umount(&Id::BatchProgress).is_ok());
+                }
+
+                None
+            }
+            InterfaceMsg::OpenTasks(tasks) => {
+                self.mount(
+                    app,
+                    Id::TaskPanel,
+                    Components::TaskPanel(TaskPanel::new(tasks)),
+                );
+                assert!(app.active(&Id::TaskPanel).is_ok());
+
+                None
+            }
+            InterfaceMsg::CloseTasks => {
+                // Focus goes back to what had it before
+                if app.mounted(&Id::TaskPanel) {
+                    assert!(app.umount(&Id::TaskPanel).is_ok());
+                }
+
+                None
+            }
+            InterfaceMsg::OpenError(report) => {
+                self.mount(
+                    app,
+                    Id::ErrorPane,
+                    Components::ErrorPane(ErrorPane::new(&report)),
+                );
+                assert!(app.active(&Id::ErrorPane).is_ok());
+
+                None
+            }
+            InterfaceMsg::CloseError => {
+                if app.mounted(&Id::ErrorPane) {
+                    assert!(app.umount(&Id::ErrorPane).is_ok());
+                }
+
+                None
+            }
+            InterfaceMsg::OpenNotifications(notifications) => {
+                self.mount(
+                    app,
+                    Id::NotificationPanel,
+                    Components::NotificationPanel(NotificationPanel::new(notifications)),
+                );
+                assert!(app.active(&Id::NotificationPanel).is_ok());
+
+                None
+            }
+            InterfaceMsg::CloseNotifications => {
+                if app.mounted(&Id::NotificationPanel) {
+                    assert!(app.umount(&Id::NotificationPanel).is_ok());
+                }
+
+                None
+            }
+            InterfaceMsg::ShowToasts(toasts) => {
+                if app.mounted(&Id::Toasts) {
+                    assert!(app.umount(&Id::Toasts).is_ok());
+                }
+                if !toasts.is_empty() {
+                    self.mount(app, Id::Toasts, Components::Toasts(Toasts::new(&toasts)));
+                }
+
+                None
+            }
+            InterfaceMsg::OpenDebug => {
+                self.mount(app, Id::DebugLog, Components::DebugLog(DebugLog::default()));
+                assert!(app.active(&Id::DebugLog).is_ok());
+
+                None
+            }
+            InterfaceMsg::CloseDebug => {
+                if app.mounted(&Id::DebugLog) {
+                    assert!(app.umount(&Id::DebugLog).is_ok());
+                }
+
+                None
+            }
+            InterfaceMsg::CloseModal => {
+                for id in [Id::DeleteModal, Id::Prompt] {
+                    if app.mounted(&id) {
+                        assert!(app.umount(&id).is_ok());
+                    }
+                }
+                assert!(app.active(&Id::ServerList).is_ok());
+
                 None
             }
         }
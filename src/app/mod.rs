@@ -1,3 +1,4 @@
 pub mod interface;
 pub mod model;
+pub mod notifications;
 pub mod tasks;
@@ -1,11 +1,23 @@
+use std::collections::BTreeMap;
 use std::time::Duration;
 
+use anyhow::Result;
+use tuirealm::props::{PropPayload, PropValue};
 use tuirealm::terminal::TerminalBridge;
 use tuirealm::{Application, AttrValue, Attribute, EventListenerCfg, Update};
 
 use crate::app::interface::Interface;
+use crate::app::notifications::{Notification, Notifications, Severity};
 use crate::app::tasks::{Task, TaskHandler, Tasks};
-use crate::constants::{Args, Config, Id, InterfaceMsg, Msg, ServerHandle, State, UserEventIter};
+use crate::components::input;
+use crate::config::Blueprint;
+use crate::constants::{
+    self, Args, Config, Id, InputId, InterfaceMsg, Msg, Prompt, ServerHandle, State, UserEvent,
+    UserEventIter,
+};
+use crate::error::ErrorReport;
+use crate::providers::{self, CreateServer, Server, UpdateServer};
+use crate::validation;
 
 pub struct Model {
     pub app: Application<Id, Msg, UserEventIter>,
@@ -14,12 +26,31 @@ pub struct Model {
     pub tasks: TaskHandler,
     pub interface: Interface,
     pub terminal: TerminalBridge,
+    pub accounts: Vec<String>,
+    pub account: Option<String>,
+    /// Servers waiting for the deletion to be confirmed
+    pub deleting: Vec<Server>,
+    /// Servers waiting for the label to add
+    pub labelling: Vec<Server>,
+    /// Server names from the list, checked against new names
+    pub servers: Vec<String>,
+    pub blueprints: BTreeMap<String, Blueprint>,
+    /// Blueprint picked on the create screen
+    pub blueprint: Option<String>,
+    /// Last task that failed, shown in the error pane
+    pub error: Option<Box<ErrorReport>>,
+    pub notifications: Notifications,
 }
 
 impl Model {
-    pub fn new(args: Args) -> Self {
-        let config = Config::new(args);
-        let task_handler = TaskHandler::new(config.clone());
+    pub fn new(args: Args) -> Result<Self> {
+        let config = Config::new(args)?;
+        let connections = config
+            .accounts
+            .iter()
+            .map(providers::connect)
+            .collect::<Result<Vec<_>>>()?;
+        let task_handler = TaskHandler::new(connections, config.refresh);
         let interface = Interface::default();
         let mut terminal = TerminalBridge::new().expect("Cannot initialize terminal");
 
@@ -32,20 +63,260 @@ impl Model {
         );
         interface.init(&mut app, &mut terminal);
 
-        Self {
+        Ok(Self {
             app,
             quit: false,
             redraw: true,
             tasks: task_handler,
             interface,
             terminal,
-        }
+            accounts: config.accounts.into_iter().map(|a| a.name).collect(),
+            account: None,
+            deleting: Vec::new(),
+            labelling: Vec::new(),
+            servers: Vec::new(),
+            blueprints: config.blueprints,
+            blueprint: None,
+            error: None,
+            notifications: Notifications::default(),
+        })
+    }
+
+    /// Background refreshes wait while the user fills the create form or
+    /// answers a modal, so nothing changes under them.
+    pub fn pause_refresh(&self) {
+        let busy = self.interface == Interface::Create
+            || self.app.mounted(&Id::DeleteModal)
+            || self.app.mounted(&Id::Prompt);
+        self.tasks.pause(busy);
     }
 
     pub fn view(&mut self) {
         self.interface.view(&mut self.app, &mut self.terminal);
     }
 
+    /// Reports something to the user: the label shows it, the history keeps
+    /// it and anything above info also goes up as a toast.
+    fn notify(&mut self, severity: Severity, message: String) {
+        self.notifications
+            .push(Notification::new(severity, message));
+        self.show_notifications();
+    }
+
+    /// Puts the latest notification and the toasts back on screen, also
+    /// after the interface was remounted.
+    fn show_notifications(&mut self) {
+        if let Some(latest) = self.notifications.latest() {
+            let text = match latest.severity {
+                Severity::Error => {
+                    format!("{} {} (Ctrl+e: details)", latest.clock(), latest.message)
+                }
+                _ => format!("{} {}", latest.clock(), latest.message),
+            };
+            assert!(self
+                .app
+                .attr(&Id::Label, Attribute::Text, AttrValue::String(text))
+                .is_ok());
+            assert!(self
+                .app
+                .attr(
+                    &Id::Label,
+                    Attribute::Foreground,
+                    AttrValue::Color(latest.severity.color())
+                )
+                .is_ok());
+        }
+
+        self.interface.perform(
+            &mut self.app,
+            InterfaceMsg::ShowToasts(self.notifications.toasts()),
+        );
+    }
+
+    /// Hands the selected account back to a freshly mounted header.
+    fn restore_account(&mut self) {
+        if let Some(account) = self.account.clone() {
+            assert!(self
+                .app
+                .attr(
+                    &Id::Header,
+                    Attribute::Custom("account"),
+                    AttrValue::String(account)
+                )
+                .is_ok());
+        }
+    }
+
+    /// The account shown in the header, or the first one.
+    fn current_account(&self) -> String {
+        self.account.clone().unwrap_or(self.accounts[0].clone())
+    }
+
+    /// Loads the create pickers for the current account.
+    fn fetch_create_options(&mut self) {
+        let user_data = self
+            .blueprints
+            .values()
+            .filter_map(|blueprint| blueprint.user_data.clone())
+            .collect();
+        self.tasks
+            .clone()
+            .add_task(Task::new(Tasks::FetchCreateOptions(
+                self.current_account(),
+                user_data,
+            )));
+    }
+
+    /// Offers the configured blueprints on a freshly mounted create screen.
+    fn offer_blueprints(&mut self) {
+        self.blueprint = None;
+        let names = std::iter::once(String::new())
+            .chain(self.blueprints.keys().cloned())
+            .map(PropValue::Str)
+            .collect();
+        assert!(self
+            .app
+            .attr(
+                &Id::Blueprint,
+                Attribute::Custom("options"),
+                AttrValue::Payload(PropPayload::Vec(names))
+            )
+            .is_ok());
+    }
+
+    /// Pre-fills the create pickers from a blueprint, or puts the account
+    /// defaults back when none is picked. The name is only suggested when empty.
+    fn apply_blueprint(&mut self, name: String) -> Option<Msg> {
+        let blueprint = self.blueprints.get(&name).cloned().unwrap_or_default();
+        self.blueprint = Some(name).filter(|name| self.blueprints.contains_key(name));
+
+        let one = |value: &Option<String>| match value {
+            Some(value) => AttrValue::String(value.clone()),
+            None => AttrValue::Payload(PropPayload::None),
+        };
+        let many = |values: Vec<String>| match values.is_empty() {
+            true => AttrValue::Payload(PropPayload::None),
+            false => AttrValue::Payload(PropPayload::Vec(
+                values.into_iter().map(PropValue::Str).collect(),
+            )),
+        };
+        let public_net = match (blueprint.ipv4, blueprint.ipv6) {
+            (None, None) => Vec::new(),
+            (ipv4, ipv6) => [
+                ("ipv4", ipv4.unwrap_or(false)),
+                ("ipv6", ipv6.unwrap_or(true)),
+            ]
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(ip, _)| ip.to_string())
+            .collect(),
+        };
+        // Datacenter "" is "Any", so a location alone is not pinned down further
+        let datacenter = match (&blueprint.location, &blueprint.datacenter) {
+            (Some(_), None) => Some(String::new()),
+            (_, datacenter) => datacenter.clone(),
+        };
+        let picks = [
+            (Id::CreateServer2, one(&blueprint.server_type)),
+            (Id::CreateServer3, one(&blueprint.image)),
+            (Id::CreateServer4, one(&blueprint.location)),
+            (Id::CreateServer5, one(&datacenter)),
+            (Id::CreateServer6, many(blueprint.ssh_keys.clone())),
+            (Id::CreateServer7, many(public_net)),
+            (Id::CreateServer8, many(blueprint.networks.clone())),
+            (Id::CreateServer9, many(blueprint.firewalls.clone())),
+            (Id::CreateServer10, one(&blueprint.user_data)),
+        ];
+        for (id, pick) in picks {
+            assert!(self.app.attr(&id, Attribute::Custom("pick"), pick).is_ok());
+        }
+        // Picking the location does not report back, offer its types here
+        assert!(self
+            .app
+            .attr(
+                &Id::CreateServer2,
+                Attribute::Custom("location"),
+                AttrValue::String(self.picked(&Id::CreateServer4))
+            )
+            .is_ok());
+
+        if let Some(name) = self.blueprint.clone() {
+            if self.picked(&Id::CreateServer1).is_empty() {
+                let suggested = Blueprint::next_name(&name, &self.servers);
+                for attr in [Attribute::Value, Attribute::Custom("state")] {
+                    assert!(self
+                        .app
+                        .attr(
+                            &Id::CreateServer1,
+                            attr,
+                            AttrValue::String(suggested.clone())
+                        )
+                        .is_ok());
+                }
+            }
+        }
+
+        // Update label
+        self.notify(
+            Severity::Info,
+            match self.blueprint.as_ref() {
+                Some(name) => format!("Using blueprint {}", name),
+                None => "Using the account defaults".to_string(),
+            },
+        );
+
+        let user_data = self.picked(&Id::CreateServer10);
+        self.interface
+            .perform(&mut self.app, InterfaceMsg::PreviewUserData(user_data))
+    }
+
+    /// What has to be typed back to delete the servers.
+    fn confirm_phrase(servers: &[Server]) -> String {
+        match servers {
+            [server] => server.name.clone(),
+            servers => format!("delete {}", servers.len()),
+        }
+    }
+
+    /// Runs a task per server in parallel, following them in the batch panel.
+    fn run_batch(&mut self, title: String, items: Vec<(String, Tasks)>) -> Option<Msg> {
+        // Update label
+        self.notify(
+            Severity::Info,
+            format!("{} on {} servers", title, items.len()),
+        );
+
+        let servers = items.iter().map(|(server, _)| server.clone()).collect();
+        self.tasks.clone().add_task(Task::new(Tasks::Batch(items)));
+
+        // Update UI
+        self.interface
+            .perform(&mut self.app, InterfaceMsg::ShowBatch(title, servers))
+    }
+
+    /// Reads the value a create field holds in its `Custom("state")` attr.
+    fn picked(&self, id: &Id) -> String {
+        match self.app.query(id, Attribute::Custom("state")) {
+            Ok(Some(AttrValue::String(value))) => value,
+            _ => String::new(),
+        }
+    }
+
+    /// Reads the values a multi-select create field holds in `Custom("state")`.
+    fn picked_many(&self, id: &Id) -> Vec<String> {
+        self.listed(id, "state")
+    }
+
+    /// Reads a list of strings a component publishes in a custom attr.
+    fn listed(&self, id: &Id, attr: &'static str) -> Vec<String> {
+        match self.app.query(id, Attribute::Custom(attr)) {
+            Ok(Some(AttrValue::Payload(PropPayload::Vec(values)))) => {
+                values.into_iter().map(|value| value.unwrap_str()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
     pub fn terminate(&mut self) {
         let _ = self.terminal.leave_alternate_screen();
         let _ = self.terminal.disable_raw_mode();
@@ -74,14 +345,7 @@ impl Update<Msg> for Model {
                 }
                 Msg::Connected => {
                     // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String("Provider connected".to_string())
-                        )
-                        .is_ok());
+                    self.notify(Severity::Info, "Provider connected".to_string());
 
                     // Update UI
                     self.interface
@@ -89,52 +353,31 @@ impl Update<Msg> for Model {
                 }
                 Msg::Disconnected => {
                     // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String("Provider disconnected".to_string())
-                        )
-                        .is_ok());
+                    self.notify(Severity::Warning, "Provider disconnected".to_string());
 
                     // Update UI
                     self.interface
                         .perform(&mut self.app, InterfaceMsg::Disconnected)
                 }
                 Msg::ChangeFocus() => {
-                    // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String("Focus changed".to_string())
-                        )
-                        .is_ok());
-
                     // Update UI
                     self.interface.change_focus(&mut self.app)
                 }
                 Msg::UpdateState(state) => {
-                    // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String(format!("State update: {:?}", state))
-                        )
-                        .is_ok());
-
                     match state {
                         State::SelectedServer(server) => {
                             match server {
                                 ServerHandle::Create => {
                                     // Update interface
                                     if self.interface != Interface::Create {
+                                        // The list is remounted, remember what it showed
+                                        self.servers = self.listed(&Id::ServerList, "names");
                                         self.interface = Interface::Create;
                                         self.interface.init(&mut self.app, &mut self.terminal);
+                                        self.restore_account();
+                                        self.show_notifications();
+                                        self.offer_blueprints();
+                                        self.fetch_create_options();
                                     }
                                     None
                                 }
@@ -143,6 +386,12 @@ impl Update<Msg> for Model {
                                     if self.interface != Interface::Status {
                                         self.interface = Interface::Status;
                                         self.interface.init(&mut self.app, &mut self.terminal);
+                                        self.restore_account();
+                                        self.show_notifications();
+                                        self.interface.perform(
+                                            &mut self.app,
+                                            InterfaceMsg::ShowActions(self.tasks.actions()),
+                                        );
                                     }
                                     self.interface.perform(
                                         &mut self.app,
@@ -154,102 +403,163 @@ impl Update<Msg> for Model {
                         State::Empty => None,
                     }
                 }
-                Msg::Input(id, input) => {
-                    // Update label
+                Msg::Input(InputId::CreateServerBlueprint, name) => self.apply_blueprint(name),
+                Msg::Input(InputId::CreateServerUserData, content) => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::PreviewUserData(content)),
+                Msg::Input(InputId::CreateServerLocation, location) => {
+                    // Update UI
                     assert!(self
                         .app
                         .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String(format!("Input from {:?}: {:?}", id, input))
+                            &Id::CreateServer2,
+                            Attribute::Custom("location"),
+                            AttrValue::String(location)
                         )
                         .is_ok());
 
                     None
                 }
+                Msg::Input(..) => None,
                 Msg::Submit => {
-                    let name = self
-                        .app
-                        .query(&Id::CreateServer1, Attribute::Custom("state"));
-                    assert!(name.is_ok());
-                    let name = name.unwrap().unwrap().unwrap_string();
-                    let srv_type = self
-                        .app
-                        .query(&Id::CreateServer2, Attribute::Custom("state"));
-                    assert!(srv_type.is_ok());
-                    let srv_type = srv_type.unwrap().unwrap().unwrap_string();
-                    let image = self
-                        .app
-                        .query(&Id::CreateServer3, Attribute::Custom("state"));
-                    assert!(image.is_ok());
-                    let image = image.unwrap().unwrap().unwrap_string();
+                    let name = self.picked(&Id::CreateServer1);
+                    let srv_type = self.picked(&Id::CreateServer2);
+                    let image = self.picked(&Id::CreateServer3);
+                    let location = self.picked(&Id::CreateServer4);
+                    let datacenter = self.picked(&Id::CreateServer5);
+                    let user_data = self.picked(&Id::CreateServer10);
+                    let ssh_keys = self.picked_many(&Id::CreateServer6);
+                    let (addresses, primary_ips): (Vec<_>, Vec<_>) = self
+                        .picked_many(&Id::CreateServer7)
+                        .into_iter()
+                        .partition(|ip| ip == "ipv4" || ip == "ipv6");
 
-                    // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String(format!(
-                                "Creating server: {}, {}, {}",
-                                name, srv_type, image
-                            ))
-                        )
-                        .is_ok());
+                    let request = CreateServer {
+                        name,
+                        server_type: srv_type,
+                        image,
+                        location: Some(location).filter(|location| !location.is_empty()),
+                        datacenter: Some(datacenter).filter(|datacenter| !datacenter.is_empty()),
+                        ssh_keys,
+                        enable_ipv4: addresses.iter().any(|ip| ip == "ipv4"),
+                        enable_ipv6: addresses.iter().any(|ip| ip == "ipv6"),
+                        primary_ips,
+                        networks: self.picked_many(&Id::CreateServer8),
+                        firewalls: self.picked_many(&Id::CreateServer9),
+                        labels: self
+                            .blueprint
+                            .as_ref()
+                            .and_then(|name| self.blueprints.get(name))
+                            .map(|blueprint| blueprint.labels.clone())
+                            .unwrap_or_default(),
+                        user_data: Some(user_data).filter(|user_data| !user_data.is_empty()),
+                    };
 
-                    if name.trim().is_empty() || !name.is_ascii() {
-                        // Update label
-                        assert!(self
-                            .app
-                            .attr(
-                                &Id::Label,
-                                Attribute::Text,
-                                AttrValue::String("Name invalid".to_string())
-                            )
-                            .is_ok());
+                    let mut errors = validation::create_server(
+                        &request,
+                        &self.listed(&Id::CreateServer2, "options"),
+                        &self.listed(&Id::CreateServer3, "options"),
+                        &self.servers,
+                    );
+                    // Stays flagged until something else is picked
+                    let missing = self
+                        .blueprint
+                        .as_ref()
+                        .and_then(|name| self.blueprints.get(name))
+                        .and_then(|blueprint| blueprint.user_data.clone())
+                        .filter(|_| {
+                            self.app
+                                .query(&Id::CreateServer10, Attribute::Custom("invalid"))
+                                .ok()
+                                .flatten()
+                                == Some(AttrValue::Flag(true))
+                        });
+                    if let Some(reference) = missing {
+                        errors.push((
+                            InputId::CreateServerUserData,
+                            format!("Cannot load the blueprint user data {}", reference),
+                        ));
                     }
 
-                    if srv_type.trim().is_empty() || !srv_type.is_ascii() {
-                        // Update label
+                    // Flag the pickers with an error, the others are valid again
+                    for (input, id) in [
+                        (InputId::CreateServerType, Id::CreateServer2),
+                        (InputId::CreateServerImage, Id::CreateServer3),
+                        (InputId::CreateServerUserData, Id::CreateServer10),
+                    ] {
+                        let invalid = errors.iter().any(|(error, _)| *error == input);
                         assert!(self
                             .app
-                            .attr(
-                                &Id::Label,
-                                Attribute::Text,
-                                AttrValue::String("Type invalid".to_string())
-                            )
+                            .attr(&id, Attribute::Custom("invalid"), AttrValue::Flag(invalid))
                             .is_ok());
                     }
 
-                    if image.trim().is_empty() || !image.is_ascii() {
+                    if let Some((input, _)) = errors.first() {
+                        let message = errors
+                            .iter()
+                            .map(|(_, message)| message.as_str())
+                            .collect::<Vec<_>>()
+                            .join("; ");
+
                         // Update label
-                        assert!(self
-                            .app
-                            .attr(
-                                &Id::Label,
-                                Attribute::Text,
-                                AttrValue::String("Image invalid".to_string())
-                            )
-                            .is_ok());
+                        self.notify(
+                            Severity::Warning,
+                            format!("Cannot create server: {}", message),
+                        );
+
+                        // Keep the name flagged until it is edited
+                        if errors
+                            .iter()
+                            .any(|(input, _)| *input == InputId::CreateServerName)
+                        {
+                            assert!(self
+                                .app
+                                .attr(
+                                    &Id::CreateServer1,
+                                    Attribute::InputType,
+                                    AttrValue::InputType(input::INVALID)
+                                )
+                                .is_ok());
+                        }
+
+                        // Update UI
+                        let id = match input {
+                            InputId::CreateServerType => Id::CreateServer2,
+                            InputId::CreateServerImage => Id::CreateServer3,
+                            InputId::CreateServerUserData => Id::CreateServer10,
+                            _ => Id::CreateServer1,
+                        };
+                        assert!(self.app.active(&id).is_ok());
+
+                        return None;
                     }
 
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        format!(
+                            "Creating server: {}, {}, {}, {}",
+                            request.name,
+                            request.server_type,
+                            request.image,
+                            request.location.as_deref().unwrap_or_default()
+                        ),
+                    );
+
+                    // Submitting twice would create a duplicate
+                    self.servers.push(request.name.clone());
+
                     // Trigger task
-                    self.tasks
-                        .clone()
-                        .add_task(Task::new(Tasks::CreateServer(name, srv_type, image)));
+                    self.tasks.clone().add_task(Task::new(Tasks::CreateServer(
+                        self.current_account(),
+                        Box::new(request),
+                    )));
 
                     None
                 }
                 Msg::UpdateProviderStatus => {
                     // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String("Provider status update issued".to_string())
-                        )
-                        .is_ok());
+                    self.notify(Severity::Info, "Provider status update issued".to_string());
 
                     // Trigger task
                     self.tasks
@@ -258,29 +568,336 @@ impl Update<Msg> for Model {
 
                     None
                 }
+                Msg::SelectAccount(account) => {
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        format!("Showing {}", account.as_deref().unwrap_or("all accounts")),
+                    );
+
+                    self.account = account;
+                    if self.interface == Interface::Create {
+                        self.fetch_create_options();
+                    }
+
+                    None
+                }
                 Msg::FetchServers => {
                     // Update label
-                    assert!(self
-                        .app
-                        .attr(
-                            &Id::Label,
-                            Attribute::Text,
-                            AttrValue::String("Fetching servers".to_string())
-                        )
-                        .is_ok());
+                    self.notify(Severity::Info, "Fetching servers".to_string());
 
                     // Trigger task
                     self.tasks.clone().add_task(Task::new(Tasks::FetchServers));
 
                     None
                 }
-                Msg::Info(msg) => {
+                Msg::RequestDelete(servers) => {
+                    let confirm = Self::confirm_phrase(&servers);
+                    let subject = match servers.len() {
+                        1 => "it".to_string(),
+                        count => format!("{} servers", count),
+                    };
+
                     // Update label
+                    self.notify(
+                        Severity::Info,
+                        format!("Type {} to confirm deletion", confirm),
+                    );
+
+                    self.deleting = servers;
+
+                    // Update UI
+                    self.interface.perform(
+                        &mut self.app,
+                        InterfaceMsg::OpenDeleteModal(confirm, subject),
+                    )
+                }
+                Msg::ConfirmDelete(answer) => {
+                    if self.deleting.is_empty() || answer != Self::confirm_phrase(&self.deleting) {
+                        // Update label
+                        self.notify(
+                            Severity::Warning,
+                            "Confirmation does not match, nothing deleted".to_string(),
+                        );
+
+                        return None;
+                    }
+
+                    let mut servers = std::mem::take(&mut self.deleting);
+                    if servers.len() == 1 {
+                        let server = servers.remove(0);
+
+                        // Update label
+                        self.notify(Severity::Info, format!("Deleting server {}", server.name));
+
+                        // Trigger task
+                        self.tasks
+                            .clone()
+                            .add_task(Task::new(Tasks::DeleteServer(server.account, server.id)));
+                    } else {
+                        let items = servers
+                            .into_iter()
+                            .map(|server| {
+                                (server.name, Tasks::DeleteServer(server.account, server.id))
+                            })
+                            .collect();
+                        self.run_batch("Delete".to_string(), items);
+                    }
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::CloseModal)
+                }
+                Msg::Power(mut servers, action) => {
+                    if servers.len() > 1 {
+                        let mut title = action.to_string();
+                        title[..1].make_ascii_uppercase();
+                        let items = servers
+                            .into_iter()
+                            .map(|server| {
+                                let task = Tasks::Power(server.account, server.id, action);
+                                (server.name, task)
+                            })
+                            .collect();
+                        return self.run_batch(title, items);
+                    }
+                    let server = servers.remove(0);
+
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        format!("Running {} on {}", action, server.name),
+                    );
+
+                    // Trigger task
+                    self.tasks.clone().add_task(Task::new(Tasks::Power(
+                        server.account,
+                        server.id,
+                        action,
+                    )));
+
+                    None
+                }
+                Msg::RequestMark => {
+                    // Update label
+                    self.notify(Severity::Info, "Type a filter to mark servers".to_string());
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::OpenPrompt(Prompt::Mark))
+                }
+                Msg::Mark(filter) => {
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        match filter.trim() {
+                            "" => "Marked all servers".to_string(),
+                            filter => format!("Marked servers matching {:?}", filter),
+                        },
+                    );
+
                     assert!(self
                         .app
-                        .attr(&Id::Label, Attribute::Text, AttrValue::String(msg))
+                        .attr(
+                            &Id::ServerList,
+                            Attribute::Custom("mark"),
+                            AttrValue::String(filter)
+                        )
                         .is_ok());
 
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::CloseModal)
+                }
+                Msg::RequestLabel(servers) => {
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        format!("Type the label to add to {} servers", servers.len()),
+                    );
+
+                    self.labelling = servers;
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::OpenPrompt(Prompt::Label))
+                }
+                Msg::ConfirmLabel(label) => {
+                    let (key, value) = match constants::parse_label(label.trim()) {
+                        Ok(label) => label,
+                        Err(err) => {
+                            // Update label
+                            self.notify(Severity::Warning, err.to_string());
+
+                            return None;
+                        }
+                    };
+
+                    let mut items = std::mem::take(&mut self.labelling)
+                        .into_iter()
+                        .map(|server| {
+                            let mut labels = server.labels.clone();
+                            labels.insert(key.clone(), value.clone());
+                            let update = UpdateServer {
+                                labels: Some(labels),
+                                ..Default::default()
+                            };
+                            let task =
+                                Tasks::UpdateServer(server.account, server.id, Box::new(update));
+                            (server.name, task)
+                        })
+                        .collect::<Vec<_>>();
+                    if items.len() == 1 {
+                        let (name, task) = items.remove(0);
+
+                        // Update label
+                        self.notify(
+                            Severity::Info,
+                            format!("Labelling {} with {}={}", name, key, value),
+                        );
+
+                        // Trigger task
+                        self.tasks.clone().add_task(Task::new(task));
+                    } else {
+                        self.run_batch(format!("Label {}={}", key, value), items);
+                    }
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::CloseModal)
+                }
+                Msg::CloseBatch => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::CloseBatch),
+                Msg::CloseModal => {
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        match self.deleting.is_empty() {
+                            true => "Cancelled".to_string(),
+                            false => "Deletion cancelled".to_string(),
+                        },
+                    );
+
+                    self.deleting.clear();
+                    self.labelling.clear();
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::CloseModal)
+                }
+                Msg::ToggleTasks => {
+                    if self.app.mounted(&Id::TaskPanel) {
+                        return Some(Msg::CloseTasks);
+                    }
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::OpenTasks(self.tasks.history()))
+                }
+                Msg::CloseTasks => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::CloseTasks),
+                Msg::Reported(events) => {
+                    for event in events {
+                        match event {
+                            UserEvent::Error(report) => {
+                                // Update label
+                                self.notify(Severity::Error, report.to_string());
+                                self.error = Some(report);
+                            }
+                            UserEvent::Notice(severity, message) => {
+                                // Update label
+                                self.notify(severity, message);
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    None
+                }
+                Msg::ToggleError => {
+                    if self.app.mounted(&Id::ErrorPane) {
+                        return Some(Msg::CloseError);
+                    }
+                    let Some(report) = self.error.clone() else {
+                        // Update label
+                        self.notify(Severity::Info, "No errors so far".to_string());
+                        return None;
+                    };
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::OpenError(report))
+                }
+                Msg::CloseError => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::CloseError),
+                Msg::CancelTask(id) => {
+                    let cancelled = self.tasks.cancel(id);
+
+                    // Update label
+                    self.notify(
+                        Severity::Info,
+                        match cancelled {
+                            true => "Task cancelled".to_string(),
+                            false => "Task already finished".to_string(),
+                        },
+                    );
+
+                    // Whatever the provider already started shows on the next fetch
+                    cancelled.then_some(Msg::FetchServers)
+                }
+                Msg::ToggleNotifications => {
+                    if self.app.mounted(&Id::NotificationPanel) {
+                        return Some(Msg::CloseNotifications);
+                    }
+
+                    // Update UI
+                    self.interface.perform(
+                        &mut self.app,
+                        InterfaceMsg::OpenNotifications(self.notifications.history()),
+                    )
+                }
+                Msg::CloseNotifications => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::CloseNotifications),
+                Msg::DismissToasts => {
+                    if !self.notifications.dismiss() {
+                        return None;
+                    }
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::ShowToasts(Vec::new()))
+                }
+                Msg::ExpireToasts => {
+                    if !self.notifications.expire() {
+                        return None;
+                    }
+
+                    // Update UI
+                    self.interface.perform(
+                        &mut self.app,
+                        InterfaceMsg::ShowToasts(self.notifications.toasts()),
+                    )
+                }
+                Msg::ToggleDebug => {
+                    if self.app.mounted(&Id::DebugLog) {
+                        return Some(Msg::CloseDebug);
+                    }
+
+                    // Update UI
+                    self.interface
+                        .perform(&mut self.app, InterfaceMsg::OpenDebug)
+                }
+                Msg::CloseDebug => self
+                    .interface
+                    .perform(&mut self.app, InterfaceMsg::CloseDebug),
+                Msg::Notify(severity, message) => {
+                    // Update label
+                    self.notify(severity, message);
+
                     None
                 }
             }
@@ -0,0 +1,215 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use tuirealm::props::Color;
+
+/// Notifications kept for the history screen
+const HISTORY: usize = 500;
+/// Toasts shown at once
+const TOASTS: usize = 4;
+/// How long toasts other than errors stay up
+const TOAST_TIME: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn color(&self) -> Color {
+        match self {
+            Severity::Info => Color::LightYellow,
+            Severity::Success => Color::LightGreen,
+            Severity::Warning => Color::Yellow,
+            Severity::Error => Color::LightRed,
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Success => write!(f, "success"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Something carton reported to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub severity: Severity,
+    pub message: String,
+    pub time: DateTime<Local>,
+}
+
+impl Notification {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            time: Local::now(),
+        }
+    }
+
+    /// Time of day it was reported at.
+    pub fn clock(&self) -> String {
+        self.time.format("%H:%M:%S").to_string()
+    }
+}
+
+/// Everything reported this session, with the toasts still shown.
+#[derive(Debug, Default)]
+pub struct Notifications {
+    history: Vec<Notification>,
+    /// Toasts with the time they went up
+    toasts: Vec<(Notification, Instant)>,
+}
+
+impl Notifications {
+    /// Records a notification, anything above info is also shown as a toast.
+    pub fn push(&mut self, notification: Notification) {
+        if self.history.len() >= HISTORY {
+            self.history.remove(0);
+        }
+        if notification.severity != Severity::Info {
+            if self.toasts.len() >= TOASTS {
+                // Make room by the oldest toast that would expire anyway
+                let oldest = self
+                    .toasts
+                    .iter()
+                    .position(|(toast, _)| toast.severity != Severity::Error)
+                    .unwrap_or(0);
+                self.toasts.remove(oldest);
+            }
+            self.toasts.push((notification.clone(), Instant::now()));
+        }
+        self.history.push(notification);
+    }
+
+    pub fn latest(&self) -> Option<&Notification> {
+        self.history.last()
+    }
+
+    pub fn history(&self) -> Vec<Notification> {
+        self.history.clone()
+    }
+
+    pub fn toasts(&self) -> Vec<Notification> {
+        self.toasts.iter().map(|(toast, _)| toast.clone()).collect()
+    }
+
+    /// Takes down toasts past their time, true when any went. Errors stay
+    /// until dismissed.
+    pub fn expire(&mut self) -> bool {
+        let before = self.toasts.len();
+        self.toasts.retain(|(toast, shown)| {
+            toast.severity == Severity::Error || shown.elapsed() < TOAST_TIME
+        });
+        self.toasts.len() != before
+    }
+
+    /// Takes down every toast, true when any was shown.
+    pub fn dismiss(&mut self) -> bool {
+        let shown = !self.toasts.is_empty();
+        self.toasts.clear();
+        shown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notify(notifications: &mut Notifications, severity: Severity, message: &str) {
+        notifications.push(Notification::new(severity, message.to_string()));
+    }
+
+    fn messages(notifications: &[Notification]) -> Vec<&str> {
+        notifications
+            .iter()
+            .map(|notification| notification.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn info_is_only_kept_in_history() {
+        let mut notifications = Notifications::default();
+        notify(&mut notifications, Severity::Info, "Refreshed");
+        notify(&mut notifications, Severity::Success, "Created web-1");
+        assert_eq!(
+            messages(&notifications.history()),
+            ["Refreshed", "Created web-1"]
+        );
+        assert_eq!(messages(&notifications.toasts()), ["Created web-1"]);
+        assert_eq!(notifications.latest().unwrap().message, "Created web-1");
+    }
+
+    #[test]
+    fn toasts_expire_except_errors() {
+        let mut notifications = Notifications::default();
+        notify(&mut notifications, Severity::Success, "Created web-1");
+        notify(&mut notifications, Severity::Error, "Cannot delete web-2");
+        notify(&mut notifications, Severity::Warning, "Slow response");
+        assert!(!notifications.expire());
+
+        // Put every toast past its time
+        for (_, shown) in &mut notifications.toasts {
+            *shown -= TOAST_TIME;
+        }
+        assert!(notifications.expire());
+        assert_eq!(messages(&notifications.toasts()), ["Cannot delete web-2"]);
+        assert!(!notifications.expire());
+
+        assert!(notifications.dismiss());
+        assert!(notifications.toasts().is_empty());
+        assert!(!notifications.dismiss());
+        assert_eq!(notifications.history().len(), 3);
+    }
+
+    #[test]
+    fn full_toasts_make_room_by_oldest_non_error() {
+        let mut notifications = Notifications::default();
+        notify(&mut notifications, Severity::Error, "error 1");
+        notify(&mut notifications, Severity::Success, "success 1");
+        notify(&mut notifications, Severity::Error, "error 2");
+        notify(&mut notifications, Severity::Warning, "warning 1");
+        notify(&mut notifications, Severity::Success, "success 2");
+        assert_eq!(
+            messages(&notifications.toasts()),
+            ["error 1", "error 2", "warning 1", "success 2"]
+        );
+
+        // With only errors up, the oldest of them goes
+        let mut notifications = Notifications::default();
+        for index in 1..=TOASTS + 1 {
+            notify(
+                &mut notifications,
+                Severity::Error,
+                &format!("error {}", index),
+            );
+        }
+        assert_eq!(notifications.toasts().len(), TOASTS);
+        assert_eq!(notifications.toasts()[0].message, "error 2");
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut notifications = Notifications::default();
+        for index in 0..HISTORY + 10 {
+            notify(&mut notifications, Severity::Info, &index.to_string());
+        }
+        let history = notifications.history();
+        assert_eq!(history.len(), HISTORY);
+        assert_eq!(history[0].message, "10");
+        assert_eq!(
+            notifications.latest().unwrap().message,
+            (HISTORY + 9).to_string()
+        );
+    }
+}
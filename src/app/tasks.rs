@@ -1,41 +1,200 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use hcloud::apis::configuration::Configuration;
-use hcloud::apis::{primary_ips_api, servers_api, ssh_keys_api};
-use hcloud::models::{CreateServerRequest, CreateServerRequestPublicNet};
+use anyhow::{anyhow, bail, Context, Result};
 use tokio::runtime::Runtime;
+use tokio::task::{AbortHandle, JoinHandle, JoinSet};
+use tracing::Instrument;
 use tuirealm::listener::{ListenerResult, Poll};
 use tuirealm::Event;
+use uuid::Uuid;
 
+use crate::app::notifications::Severity;
+use crate::cloud_init;
 use crate::constants::{
-    Config, ProviderStatus, ServerHandle, ServerListStatus, UserEvent, UserEventIter,
+    CreateOptions, ProviderStatus, ServerHandle, ServerListStatus, UserEvent, UserEventIter,
+};
+use crate::error::{CartonError, ErrorReport};
+use crate::providers::{
+    Action, ActionStatus, Connection, CreateServer, PowerAction, Server, UpdateServer,
 };
 
-const IMAGE: &str = "fedora-41";
-const LOCATION: &str = "fsn1";
+/// Finished tasks kept for the task panel
+const HISTORY: usize = 100;
+/// Wait between two looks at a running provider action
+const ACTION_POLL: Duration = Duration::from_secs(1);
+/// Longest wait between background refreshes that keep failing
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
 
 #[derive(Debug, Clone)]
 pub enum Tasks {
     ProviderStatus,
     FetchServers,
-    #[allow(dead_code)]
-    CreateServer(String, String, String),
+    /// Everything the account offers for new servers, and the user data the
+    /// blueprints refer to
+    FetchCreateOptions(String, Vec<String>),
+    /// Account and request of the server to create
+    CreateServer(String, Box<CreateServer>),
+    /// Account and id of the server to change in place
+    UpdateServer(String, String, Box<UpdateServer>),
+    /// Account and id of the server to delete
+    DeleteServer(String, String),
+    /// Account and id of the server to run the power action on
+    Power(String, String, PowerAction),
+    /// Server names and the task to run for each. The task handler runs them
+    /// in parallel and reports each one, run on its own they go one by one.
+    Batch(Vec<(String, Tasks)>),
     Nop,
 }
 
+impl Tasks {
+    /// How long the task may run before it is given up, tasks waiting on
+    /// provider actions get longer.
+    fn timeout(&self) -> Duration {
+        match self {
+            Tasks::CreateServer(..)
+            | Tasks::UpdateServer(..)
+            | Tasks::DeleteServer(..)
+            | Tasks::Power(..) => Duration::from_secs(600),
+            // The items run side by side, so as long as the slowest one
+            Tasks::Batch(items) => items
+                .iter()
+                .map(|(_, request)| request.timeout())
+                .max()
+                .unwrap_or_default(),
+            _ => Duration::from_secs(60),
+        }
+    }
+
+    /// Variant name, logged as the type of the request.
+    fn kind(&self) -> &'static str {
+        match self {
+            Tasks::ProviderStatus => "ProviderStatus",
+            Tasks::FetchServers => "FetchServers",
+            Tasks::FetchCreateOptions(..) => "FetchCreateOptions",
+            Tasks::CreateServer(..) => "CreateServer",
+            Tasks::UpdateServer(..) => "UpdateServer",
+            Tasks::DeleteServer(..) => "DeleteServer",
+            Tasks::Power(..) => "Power",
+            Tasks::Batch(_) => "Batch",
+            Tasks::Nop => "Nop",
+        }
+    }
+
+    /// Whether the task changes servers, so finishing it is worth a notice.
+    fn changes(&self) -> bool {
+        matches!(
+            self,
+            Tasks::CreateServer(..)
+                | Tasks::UpdateServer(..)
+                | Tasks::DeleteServer(..)
+                | Tasks::Power(..)
+        )
+    }
+}
+
+impl std::fmt::Display for Tasks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tasks::ProviderStatus => write!(f, "Provider status"),
+            Tasks::FetchServers => write!(f, "Fetch servers"),
+            Tasks::FetchCreateOptions(account, _) => {
+                write!(f, "Fetch create options of {}", account)
+            }
+            Tasks::CreateServer(account, request) => {
+                write!(f, "Create {} on {}", request.name, account)
+            }
+            Tasks::UpdateServer(account, id, _) => {
+                write!(f, "Update server {} of {}", id, account)
+            }
+            Tasks::DeleteServer(account, id) => write!(f, "Delete server {} of {}", id, account),
+            Tasks::Power(account, id, action) => {
+                write!(f, "Run {} on server {} of {}", action, id, account)
+            }
+            Tasks::Batch(items) => write!(f, "Batch of {} tasks", items.len()),
+            Tasks::Nop => write!(f, "Nothing"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskStatus {
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+    TimedOut,
+}
+
+impl std::fmt::Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskStatus::Running => write!(f, "running"),
+            TaskStatus::Done => write!(f, "done"),
+            TaskStatus::Failed(err) => write!(f, "failed: {}", err),
+            TaskStatus::Cancelled => write!(f, "cancelled"),
+            TaskStatus::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A task as listed in the task panel, sent again on every change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub status: TaskStatus,
+    /// Steps done out of the total, for tasks made of several calls
+    pub progress: Option<(usize, usize)>,
+    pub started: Instant,
+    pub finished: Option<Instant>,
+}
+
+impl TaskInfo {
+    pub fn elapsed(&self) -> Duration {
+        self.finished.unwrap_or_else(Instant::now) - self.started
+    }
+}
+
+/// A provider action as listed in the action log, sent again on every poll.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionInfo {
+    pub account: String,
+    /// What the action runs on, the server name when known
+    pub subject: String,
+    pub action: Action,
+}
+
 #[derive(Debug, Clone)]
 pub struct Task {
+    pub id: Uuid,
     pub request: Tasks,
     pub response: Option<UserEvent>,
+    pub timeout: Duration,
+    /// Shown in the task panel instead of the request
+    pub name: Option<String>,
+    /// Batch items are reported by their batch, not on their own
+    silent: bool,
+    /// Fails on an account that cannot list its servers instead of leaving
+    /// it out, for headless runs
+    pub strict: bool,
+    progress: Option<Engine>,
 }
 
 impl Default for Task {
     fn default() -> Self {
         Self {
+            id: Uuid::new_v4(),
             request: Tasks::Nop,
             response: None,
+            timeout: Tasks::Nop.timeout(),
+            name: None,
+            silent: false,
+            strict: false,
+            progress: None,
         }
     }
 }
@@ -43,98 +202,249 @@ impl Default for Task {
 impl Task {
     pub fn new(request: Tasks) -> Self {
         Self {
+            timeout: request.timeout(),
             request,
             ..Default::default()
         }
     }
 
-    async fn run(&mut self, config: Config) -> Result<()> {
+    fn name(&self) -> String {
+        self.name.clone().unwrap_or(self.request.to_string())
+    }
+
+    /// Reports how far the task got, only the task handler listens.
+    fn step(&self, done: usize, total: usize) {
+        if let Some(engine) = &self.progress {
+            engine.update(self.id, |info| info.progress = Some((done, total)));
+        }
+    }
+
+    /// Shows the servers listed so far, only the task handler listens.
+    fn stream(&self, servers: Vec<ServerHandle>) {
+        if let Some(engine) = &self.progress {
+            engine.push(UserEvent::ServerPage(ServerListStatus::new(servers)));
+        }
+    }
+
+    /// Span the task's log events are recorded in.
+    pub fn span(&self) -> tracing::Span {
+        tracing::info_span!("task", id = %self.id, kind = self.request.kind())
+    }
+
+    /// Tells the user about a problem the task got past, only the task
+    /// handler listens.
+    fn warn(&self, message: String) {
+        tracing::warn!("{}", message);
+        if let Some(engine) = &self.progress {
+            engine.push(UserEvent::Notice(Severity::Warning, message));
+        }
+    }
+
+    pub async fn run(&mut self, connections: &[Connection]) -> Result<()> {
         match &self.request {
             Tasks::ProviderStatus => {
-                let mut overview = ProviderStatus::new(config.auth.auth.clone());
+                let mut statuses = Vec::new();
+                for (done, connection) in connections.iter().enumerate() {
+                    let provider = connection.provider.as_ref();
+                    let overview = match provider.status().await {
+                        Ok(overview) => overview,
+                        Err(err) => {
+                            let error = CartonError::classify(&err);
+                            ProviderStatus {
+                                status: format!("Disconnected, {}: {}", error.title(), error),
+                                ..ProviderStatus::new(provider.platform())
+                            }
+                        }
+                    };
+                    statuses.push(ProviderStatus {
+                        account: connection.account.clone(),
+                        ..overview
+                    });
+                    self.step(done + 1, connections.len());
+                }
 
-                let mut configuration = Configuration::new();
-                configuration.bearer_access_token = Some(config.auth.token.to_string());
+                self.response = Some(UserEvent::ProviderStatus(statuses));
+            }
+            Tasks::FetchServers => {
+                let servers = Mutex::new(Vec::new());
+                for (done, connection) in connections.iter().enumerate() {
+                    let listed = servers.lock().unwrap().len();
+                    let result = connection
+                        .provider
+                        .list_server_pages(&|page| {
+                            let mut servers = servers.lock().unwrap();
+                            servers.extend(page.into_iter().map(|server| {
+                                ServerHandle::Server(Box::new(Server {
+                                    account: connection.account.clone(),
+                                    ..server
+                                }))
+                            }));
+                            self.stream(servers.clone());
+                        })
+                        .await;
+                    // The servers of an account that fails to list are left out, saying so
+                    if let Err(err) = result {
+                        if self.strict {
+                            return Err(err.context(format!(
+                                "Cannot list servers on {}",
+                                connection.account
+                            )));
+                        }
+                        servers.lock().unwrap().truncate(listed);
+                        let error = CartonError::classify(&err);
+                        self.warn(format!(
+                            "Cannot list servers on {}, {}: {}",
+                            connection.account,
+                            error.title(),
+                            error
+                        ));
+                    }
+                    self.step(done + 1, connections.len());
+                }
+
+                self.response = Some(UserEvent::ServerListStatus(ServerListStatus::new(
+                    servers.into_inner().unwrap(),
+                )));
+            }
+            Tasks::FetchCreateOptions(account, user_data) => {
+                let connection = find_connection(connections, account)?;
+                let provider = connection.provider.as_ref();
+
+                let steps = 8;
+                let server_types = provider
+                    .list_server_types()
+                    .await
+                    .context("Cannot fetch server types")?;
+                self.step(1, steps);
+                let images = provider
+                    .list_images()
+                    .await
+                    .context("Cannot fetch images")?;
+                self.step(2, steps);
+                let locations = provider
+                    .list_locations()
+                    .await
+                    .context("Cannot fetch locations")?;
+                self.step(3, steps);
+                let datacenters = provider
+                    .list_datacenters()
+                    .await
+                    .context("Cannot fetch datacenters")?;
+                self.step(4, steps);
+                let ssh_keys = provider
+                    .list_ssh_keys()
+                    .await
+                    .context("Cannot fetch ssh keys")?;
+                self.step(5, steps);
+                let primary_ips = provider
+                    .list_primary_ips()
+                    .await
+                    .context("Cannot fetch primary IPs")?;
+                self.step(6, steps);
+                let networks = provider
+                    .list_networks()
+                    .await
+                    .context("Cannot fetch networks")?;
+                self.step(7, steps);
+                let firewalls = provider
+                    .list_firewalls()
+                    .await
+                    .context("Cannot fetch firewalls")?;
+                self.step(8, steps);
 
-                let resp = servers_api::list_servers(&configuration, Default::default()).await;
-                if resp.is_ok() {
-                    overview.servers = resp.unwrap().servers.len();
-                } else {
-                    overview.status = format!("Disconnected, Error: {:?}", resp.err().unwrap());
+                let options = CreateOptions {
+                    server_types,
+                    images,
+                    locations,
+                    datacenters,
+                    ssh_keys,
+                    primary_ips,
+                    networks,
+                    firewalls,
+                    templates: cloud_init::offered(&connection.defaults, user_data)
+                        .context("Cannot read cloud-init templates")?,
+                    defaults: connection.defaults.clone(),
+                };
+
+                self.response = Some(UserEvent::CreateOptions(Box::new(options)));
+            }
+            Tasks::CreateServer(account, request) => {
+                let connection = find_connection(connections, account)?;
+                let (server, actions) = connection
+                    .provider
+                    .create_server(*request.clone())
+                    .await
+                    .context("Cannot create server")?;
+                for action in actions {
+                    self.track(connection, &server.name, &action)
+                        .await
+                        .context("Server creation failed")?;
                 }
 
-                let resp =
-                    primary_ips_api::list_primary_ips(&configuration, Default::default()).await;
-                if resp.is_ok() {
-                    overview.primary_ips = resp.unwrap().primary_ips.len();
-                } else {
-                    overview.status = format!("Disconnected, Error: {:?}", resp.err().unwrap());
+                self.response = Some(UserEvent::Refresh);
+            }
+            Tasks::UpdateServer(account, id, update) => {
+                let connection = find_connection(connections, account)?;
+                let provider = connection.provider.as_ref();
+                if let Some(labels) = update.labels.clone() {
+                    provider
+                        .set_labels(id, labels)
+                        .await
+                        .context("Cannot change labels")?;
+                }
+                if let Some(server_type) = update.server_type.as_ref() {
+                    let action = provider
+                        .change_type(id, server_type)
+                        .await
+                        .context("Cannot change server type")?;
+                    if let Some(action) = action {
+                        self.track(connection, &format!("server {}", id), &action)
+                            .await
+                            .context("Server type change failed")?;
+                    }
                 }
 
-                self.response = Some(UserEvent::ProviderStatus(overview));
+                self.response = Some(UserEvent::Refresh);
             }
-            Tasks::FetchServers => {
-                let mut configuration = Configuration::new();
-                configuration.bearer_access_token = Some(config.auth.token.to_string());
-
-                let resp = servers_api::list_servers(&configuration, Default::default()).await;
-                if resp.is_ok() {
-                    self.response = Some(UserEvent::ServerListStatus(ServerListStatus::new(
-                        resp.unwrap()
-                            .servers
-                            .iter()
-                            .map(|s| ServerHandle::Hetzner(Box::new(s.clone())))
-                            .collect(),
-                    )));
+            Tasks::DeleteServer(account, id) => {
+                let connection = find_connection(connections, account)?;
+                let action = connection
+                    .provider
+                    .delete_server(id)
+                    .await
+                    .context("Cannot delete server")?;
+                if let Some(action) = action {
+                    self.track(connection, &format!("server {}", id), &action)
+                        .await
+                        .context("Server deletion failed")?;
                 }
+
+                self.response = Some(UserEvent::Refresh);
             }
-            Tasks::CreateServer(name, server, _) => {
-                let mut configuration = Configuration::new();
-                configuration.bearer_access_token = Some(config.auth.token.to_string());
-
-                let ssh_keys =
-                    ssh_keys_api::list_ssh_keys(&configuration, Default::default()).await;
-                if ssh_keys.is_err() {
-                    self.response = Some(UserEvent::Error(format!(
-                        "Cannot fetch ssh keys: {:?}",
-                        ssh_keys.as_ref().err().unwrap()
-                    )));
-                    return Ok(());
+            Tasks::Power(account, id, action) => {
+                let connection = find_connection(connections, account)?;
+                let pending = connection
+                    .provider
+                    .power(id, *action)
+                    .await
+                    .with_context(|| format!("Cannot {} server", action))?;
+                if let Some(pending) = pending {
+                    self.track(connection, &format!("server {}", id), &pending)
+                        .await
+                        .with_context(|| format!("Server {} failed", action))?;
                 }
-                let ssh_keys: Vec<String> = ssh_keys
-                    .unwrap()
-                    .ssh_keys
-                    .into_iter()
-                    .map(|ssh_key| ssh_key.name)
-                    .collect();
-
-                let request = CreateServerRequest {
-                    name: name.to_string(),
-                    server_type: server.to_string(),
-                    start_after_create: Some(true),
-                    image: IMAGE.to_string(),
-                    ssh_keys: Some(ssh_keys.clone()),
-                    location: Some(LOCATION.to_string()),
-                    public_net: Some(Box::new(CreateServerRequestPublicNet {
-                        enable_ipv4: Some(false),
-                        ..Default::default()
-                    })),
-                    ..Default::default()
-                };
 
-                // execute request and store server ID
-                let params = servers_api::CreateServerParams {
-                    create_server_request: Some(request),
-                };
-                let server = servers_api::create_server(&configuration, params).await;
-                if server.is_err() {
-                    self.response = Some(UserEvent::Error(format!(
-                        "Cannot create server: {:?}",
-                        server.as_ref().err().unwrap()
-                    )));
-                } else {
-                    self.response = Some(UserEvent::Refresh);
+                self.response = Some(UserEvent::Refresh);
+            }
+            Tasks::Batch(items) => {
+                for (done, (server, request)) in items.iter().enumerate() {
+                    Box::pin(Task::new(request.clone()).run(connections))
+                        .await
+                        .with_context(|| format!("Batch failed on {}", server))?;
+                    self.step(done + 1, items.len());
                 }
+
+                self.response = Some(UserEvent::Refresh);
             }
             Tasks::Nop => {
                 self.response = Some(UserEvent::Empty);
@@ -144,54 +454,562 @@ impl Task {
     }
 }
 
+impl Task {
+    /// Polls a provider action until it finishes, reporting its progress to
+    /// the action log. A failed action fails the task.
+    async fn track(&self, connection: &Connection, subject: &str, id: &str) -> Result<()> {
+        let provider = connection.provider.as_ref();
+        loop {
+            let Some(action) = provider
+                .get_action(id)
+                .await
+                .context("Cannot fetch action")?
+            else {
+                return provider.wait_action(id).await;
+            };
+            tracing::debug!(
+                action = %action.id,
+                progress = action.progress,
+                "{} {}",
+                action.command,
+                action.status
+            );
+            if let Some(engine) = &self.progress {
+                engine.log_action(ActionInfo {
+                    account: connection.account.clone(),
+                    subject: subject.to_string(),
+                    action: action.clone(),
+                });
+            }
+            match action.status {
+                ActionStatus::Running => tokio::time::sleep(ACTION_POLL).await,
+                ActionStatus::Success => return Ok(()),
+                ActionStatus::Error(err) => bail!("Action {} failed: {}", action.command, err),
+            }
+        }
+    }
+}
+
+fn find_connection<'a>(connections: &'a [Connection], account: &str) -> Result<&'a Connection> {
+    connections
+        .iter()
+        .find(|connection| connection.account == account)
+        .ok_or(anyhow!("Unknown account {}", account))
+}
+
+/// Shared state of the running tasks: events waiting to be polled, what the
+/// task panel lists, and how to cancel what still runs.
+#[derive(Debug, Clone, Default)]
+struct Engine {
+    connections: Arc<Vec<Connection>>,
+    events: Arc<Mutex<Vec<UserEvent>>>,
+    history: Arc<Mutex<Vec<TaskInfo>>>,
+    actions: Arc<Mutex<Vec<ActionInfo>>>,
+    running: Arc<Mutex<HashMap<Uuid, AbortHandle>>>,
+    /// Ids of the items each running batch started
+    batches: Arc<Mutex<HashMap<Uuid, Vec<Uuid>>>>,
+    /// Skips background refreshes while set
+    paused: Arc<AtomicBool>,
+}
+
+impl Engine {
+    fn push(&self, event: UserEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn record(&self, info: TaskInfo) {
+        let mut history = self.history.lock().unwrap();
+        if history.len() >= HISTORY {
+            if let Some(oldest) = history
+                .iter()
+                .position(|info| info.status != TaskStatus::Running)
+            {
+                history.remove(oldest);
+            }
+        }
+        history.push(info.clone());
+        self.push(UserEvent::TaskProgress(info));
+    }
+
+    fn log_action(&self, info: ActionInfo) {
+        let mut actions = self.actions.lock().unwrap();
+        let logged = actions
+            .iter_mut()
+            .find(|logged| logged.account == info.account && logged.action.id == info.action.id);
+        match logged {
+            Some(logged) => *logged = info.clone(),
+            None => {
+                if actions.len() >= HISTORY {
+                    actions.remove(0);
+                }
+                actions.push(info.clone());
+            }
+        }
+        self.push(UserEvent::ActionProgress(info));
+    }
+
+    fn update(&self, id: Uuid, change: impl FnOnce(&mut TaskInfo)) {
+        let mut history = self.history.lock().unwrap();
+        if let Some(info) = history.iter_mut().find(|info| info.id == id) {
+            change(info);
+            self.push(UserEvent::TaskProgress(info.clone()));
+        }
+    }
+
+    /// Spawns the task on the runtime, the handle yields its error, if any.
+    fn start(&self, mut task: Task) -> JoinHandle<Option<String>> {
+        self.record(TaskInfo {
+            id: task.id,
+            name: task.name(),
+            status: TaskStatus::Running,
+            progress: None,
+            started: Instant::now(),
+            finished: None,
+        });
+        task.progress = Some(self.clone());
+        let id = task.id;
+
+        // Held until the handle is stored, so the task cannot finish before
+        let mut running = self.running.lock().unwrap();
+        let engine = self.clone();
+        let span = task.span();
+        let handle = tokio::spawn(
+            async move {
+                let started = Instant::now();
+                tracing::debug!("{} started", task.name());
+                let mut report = None;
+                let status = match task.request.clone() {
+                    Tasks::Batch(items) => match engine.fan_out(id, items, task.timeout).await {
+                        true => {
+                            task.response = Some(UserEvent::Refresh);
+                            TaskStatus::Done
+                        }
+                        false => TaskStatus::TimedOut,
+                    },
+                    _ => {
+                        let connections = engine.connections.clone();
+                        match tokio::time::timeout(task.timeout, task.run(&connections)).await {
+                            Ok(Ok(())) => TaskStatus::Done,
+                            Ok(Err(err)) => {
+                                report = Some(ErrorReport::new(task.name(), &err));
+                                TaskStatus::Failed(format!("{:#}", err))
+                            }
+                            Err(_) => TaskStatus::TimedOut,
+                        }
+                    }
+                };
+
+                let elapsed_ms = started.elapsed().as_millis() as u64;
+                match &status {
+                    TaskStatus::Done => tracing::info!(elapsed_ms, "{} finished", task.name()),
+                    TaskStatus::Failed(err) => tracing::error!(
+                        elapsed_ms,
+                        error = report.as_ref().map(|report| report.error.title()),
+                        "{} failed: {}",
+                        task.name(),
+                        err
+                    ),
+                    status => tracing::warn!(elapsed_ms, "{} {}", task.name(), status),
+                }
+                engine.finish(task, status, report)
+            }
+            .instrument(span),
+        );
+        running.insert(id, handle.abort_handle());
+        handle
+    }
+
+    /// Records how a task ended and hands its response or error to the UI,
+    /// returning the error of a silent task instead.
+    fn finish(
+        &self,
+        task: Task,
+        status: TaskStatus,
+        report: Option<ErrorReport>,
+    ) -> Option<String> {
+        self.running.lock().unwrap().remove(&task.id);
+        self.batches.lock().unwrap().remove(&task.id);
+        self.update(task.id, |info| {
+            // A cancelled task keeps saying so
+            if info.status == TaskStatus::Running {
+                info.status = status.clone();
+                info.finished = Some(Instant::now());
+            }
+        });
+
+        let error = |error: CartonError| ErrorReport {
+            context: task.name(),
+            details: error.to_string(),
+            error,
+        };
+        let report = match status {
+            TaskStatus::Done => None,
+            TaskStatus::TimedOut => Some(error(CartonError::Network(format!(
+                "Timed out after {}s",
+                task.timeout.as_secs()
+            )))),
+            TaskStatus::Failed(err) => report.or(Some(error(CartonError::Provider(err)))),
+            other => Some(error(CartonError::Provider(other.to_string()))),
+        };
+        if task.silent {
+            return report.map(|report| report.error.to_string());
+        }
+        match report {
+            None => {
+                if task.request.changes() {
+                    self.push(UserEvent::Notice(
+                        Severity::Success,
+                        format!("{} finished", task.name()),
+                    ));
+                }
+                if let Some(response) = task.response {
+                    self.push(response);
+                }
+                None
+            }
+            Some(report) => {
+                let err = report.error.to_string();
+                self.push(UserEvent::Error(Box::new(report)));
+                Some(err)
+            }
+        }
+    }
+
+    /// Refreshes provider statuses and servers in the background. A failed
+    /// refresh, rate limited ones included, doubles the wait until one works.
+    async fn refresh_every(self, every: Duration) {
+        let mut wait = every;
+        loop {
+            tokio::time::sleep(wait).await;
+            if self.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+            wait = match self
+                .refresh()
+                .instrument(tracing::info_span!("refresh"))
+                .await
+            {
+                true => every,
+                false => {
+                    let wait = (wait * 2).min(MAX_BACKOFF.max(every));
+                    tracing::warn!("Background refresh failed, next in {}s", wait.as_secs());
+                    wait
+                }
+            };
+        }
+    }
+
+    /// Fetches what the header and the server list show, true when every
+    /// account answered.
+    async fn refresh(&self) -> bool {
+        let mut status = Task::new(Tasks::ProviderStatus);
+        let mut servers = Task::new(Tasks::FetchServers);
+        let refreshed = tokio::time::timeout(status.timeout, async {
+            status.run(&self.connections).await?;
+            servers.run(&self.connections).await
+        })
+        .await;
+        if !matches!(refreshed, Ok(Ok(()))) {
+            return false;
+        }
+
+        match (status.response, servers.response) {
+            (
+                Some(UserEvent::ProviderStatus(statuses)),
+                Some(UserEvent::ServerListStatus(list)),
+            ) => {
+                // The last servers listed stay up while an account fails
+                let connected = statuses.iter().all(|status| status.is_connected());
+                self.push(UserEvent::Polled(statuses, connected.then_some(list)));
+                connected
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the items of a batch as tasks of their own, reporting each as it
+    /// finishes. Items still running after `timeout` are cancelled and
+    /// reported so, false then.
+    async fn fan_out(&self, batch: Uuid, items: Vec<(String, Tasks)>, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut running = self.start_items(batch, items);
+        let mut timed_out = false;
+        loop {
+            let finished = if timed_out {
+                running.join_next().await
+            } else {
+                match tokio::time::timeout_at(deadline, running.join_next()).await {
+                    Ok(finished) => finished,
+                    Err(_) => {
+                        timed_out = true;
+                        self.cancel_items(batch);
+                        continue;
+                    }
+                }
+            };
+            let Some(finished) = finished else {
+                return !timed_out;
+            };
+            let (server, err) =
+                finished.unwrap_or_else(|err| ("batch".to_string(), Some(err.to_string())));
+            self.push(UserEvent::BatchProgress(server, err));
+        }
+    }
+
+    /// Starts the items of a batch, unless it was cancelled already, and
+    /// waits on each for its error.
+    fn start_items(
+        &self,
+        batch: Uuid,
+        items: Vec<(String, Tasks)>,
+    ) -> JoinSet<(String, Option<String>)> {
+        let mut running = JoinSet::new();
+        // Held while the items start, so a cancel sees all of them or none
+        let mut batches = self.batches.lock().unwrap();
+        if !self.running.lock().unwrap().contains_key(&batch) {
+            return running;
+        }
+        let started = batches.entry(batch).or_default();
+        for (server, request) in items {
+            let task = Task {
+                name: Some(format!("{} ({})", request, server)),
+                silent: true,
+                ..Task::new(request)
+            };
+            started.push(task.id);
+            let handle = self.start(task);
+            running.spawn(async move {
+                let err = handle
+                    .await
+                    .unwrap_or_else(|_| Some(TaskStatus::Cancelled.to_string()));
+                (server, err)
+            });
+        }
+        running
+    }
+
+    fn cancel(&self, id: Uuid) -> bool {
+        let Some(handle) = self.running.lock().unwrap().remove(&id) else {
+            return false;
+        };
+        handle.abort();
+        self.cancel_items(id);
+        tracing::info!(%id, "Task cancelled");
+        self.update(id, |info| {
+            if info.status == TaskStatus::Running {
+                info.status = TaskStatus::Cancelled;
+                info.finished = Some(Instant::now());
+            }
+        });
+        true
+    }
+
+    /// Stops the items of a batch, which run on after the batch itself is
+    /// aborted.
+    fn cancel_items(&self, batch: Uuid) {
+        let items = self.batches.lock().unwrap().remove(&batch);
+        for id in items.unwrap_or_default() {
+            self.cancel(id);
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct TaskHandler {
     tx: Option<mpsc::Sender<Task>>,
-    completed: Arc<Mutex<Vec<Task>>>,
+    engine: Engine,
 }
 
 impl TaskHandler {
-    pub fn new(config: Config) -> Self {
+    pub fn new(connections: Vec<Connection>, refresh: Option<Duration>) -> Self {
         let (tx, rx) = mpsc::channel::<Task>();
-        let store = Arc::new(Mutex::new(Vec::new()));
+        let engine = Engine {
+            connections: Arc::new(connections),
+            ..Default::default()
+        };
 
-        let inner_store = store.clone();
+        // Tasks run concurrently, a slow one no longer holds up the others
+        let inner = engine.clone();
         thread::spawn(move || {
             let rt = Runtime::new().unwrap();
-            rt.block_on(async {
-                while let Ok(mut task) = rx.recv() {
-                    if let Err(err) = task.run(config.clone()).await {
-                        task.response = Some(UserEvent::Error(err.to_string()));
-                    }
-                    let mut store = inner_store.lock().unwrap();
-                    store.push(task);
-                }
-            })
+            let _guard = rt.enter();
+            if let Some(every) = refresh {
+                tokio::spawn(inner.clone().refresh_every(every));
+            }
+            while let Ok(task) = rx.recv() {
+                inner.start(task);
+            }
         });
 
         Self {
             tx: Some(tx),
-            completed: store,
+            engine,
         }
     }
 
     pub fn add_task(self, task: Task) {
         self.tx.unwrap().send(task).expect("cannot send");
     }
+
+    /// Holds background refreshes back, e.g. while the user is typing.
+    pub fn pause(&self, paused: bool) {
+        self.engine.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Stops a running task, whatever the provider already started keeps going.
+    pub fn cancel(&self, id: Uuid) -> bool {
+        self.engine.cancel(id)
+    }
+
+    /// Provider actions seen by the tasks, oldest first.
+    pub fn actions(&self) -> Vec<ActionInfo> {
+        self.engine.actions.lock().unwrap().clone()
+    }
+
+    /// Running and recently finished tasks, oldest first.
+    pub fn history(&self) -> Vec<TaskInfo> {
+        self.engine.history.lock().unwrap().clone()
+    }
 }
 
 impl Poll<UserEventIter> for TaskHandler {
     fn poll(&mut self) -> ListenerResult<Option<Event<UserEventIter>>> {
-        let mut completed = self.completed.lock().unwrap();
-        if completed.is_empty() {
+        let mut events = self.engine.events.lock().unwrap();
+        if events.is_empty() {
             return Ok(None);
         }
 
-        let events = completed
-            .drain(..)
-            .filter_map(|task| task.response)
-            .collect::<Vec<_>>();
+        let events = events.drain(..).collect::<Vec<_>>();
 
         Ok(Some(Event::User(UserEventIter::new(events))))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::constants::{Auth, AuthPlatform, Defaults};
+    use crate::providers::hetzner::Hetzner;
+
+    /// An engine whose one account accepts connections but never answers, so
+    /// its tasks run until they are cancelled or time out.
+    fn engine(listener: &TcpListener) -> Engine {
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let auth = Auth::new(
+            AuthPlatform::Hetzner,
+            "token".to_string(),
+            None,
+            Some(endpoint),
+        );
+        Engine {
+            connections: Arc::new(vec![Connection {
+                account: "silent".to_string(),
+                defaults: Defaults::default(),
+                provider: Arc::new(Hetzner::new(&auth)),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn batch() -> Task {
+        let power =
+            |id: &str| Tasks::Power("silent".to_string(), id.to_string(), PowerAction::PowerOn);
+        Task::new(Tasks::Batch(vec![
+            ("web-1".to_string(), power("1")),
+            ("web-2".to_string(), power("2")),
+        ]))
+    }
+
+    fn status(engine: &Engine, id: Uuid) -> TaskStatus {
+        let history = engine.history.lock().unwrap();
+        history
+            .iter()
+            .find(|info| info.id == id)
+            .unwrap()
+            .status
+            .clone()
+    }
+
+    /// Waits until the batch started its items, returning their ids.
+    async fn items(engine: &Engine, batch: Uuid) -> Vec<Uuid> {
+        loop {
+            if let Some(items) = engine.batches.lock().unwrap().get(&batch) {
+                return items.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn batch_timeout_follows_slowest_item() {
+        assert_eq!(batch().timeout, Duration::from_secs(600));
+        assert_eq!(Task::new(Tasks::Batch(Vec::new())).timeout, Duration::ZERO);
+    }
+
+    #[test]
+    fn cancelling_batch_aborts_its_items() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let engine = engine(&listener);
+        let task = batch();
+        let id = task.id;
+        Runtime::new().unwrap().block_on(async {
+            let handle = engine.start(task);
+            let items = items(&engine, id).await;
+            assert_eq!(items.len(), 2);
+
+            assert!(engine.cancel(id));
+            assert!(handle.await.unwrap_err().is_cancelled());
+            assert!(engine.running.lock().unwrap().is_empty());
+            assert!(engine.batches.lock().unwrap().is_empty());
+            for id in [id].iter().chain(&items) {
+                assert_eq!(status(&engine, *id), TaskStatus::Cancelled);
+            }
+            assert!(!engine.cancel(id));
+        });
+    }
+
+    #[test]
+    fn timed_out_batch_reports_unfinished_items() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let engine = engine(&listener);
+        let task = Task {
+            timeout: Duration::from_millis(200),
+            ..batch()
+        };
+        let id = task.id;
+        let err = Runtime::new().unwrap().block_on(async {
+            let handle = engine.start(task);
+            let items = items(&engine, id).await;
+            let err = handle.await.unwrap();
+            for item in items {
+                assert_eq!(status(&engine, item), TaskStatus::Cancelled);
+            }
+            err
+        });
+
+        assert!(err.unwrap().contains("Timed out"));
+        assert_eq!(status(&engine, id), TaskStatus::TimedOut);
+        assert!(engine.running.lock().unwrap().is_empty());
+        let mut reported = engine
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                UserEvent::BatchProgress(server, err) => Some((server.clone(), err.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        reported.sort();
+        let cancelled = Some(TaskStatus::Cancelled.to_string());
+        assert_eq!(
+            reported,
+            [
+                ("web-1".to_string(), cancelled.clone()),
+                ("web-2".to_string(), cancelled)
+            ]
+        );
+    }
+}
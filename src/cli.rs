@@ -0,0 +1,561 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use tracing::Instrument;
+
+use crate::app::tasks::{Task, Tasks};
+use crate::cloud_init;
+use crate::config::{Blueprint, ConfigFile};
+use crate::constants::{
+    Args, AuthCommand, Command, Config, CreateCommand, FleetCommand, Output, ServerHandle,
+    ServersCommand, UserEvent,
+};
+use crate::credentials;
+use crate::fleet::{self, Change, Fleet};
+use crate::providers::{self, Connection, CreateServer, PowerAction, Server, ServerState};
+use crate::validation;
+
+/// How long a fleet resize waits for a shutdown before it cuts the power
+const SHUTDOWN_WAIT: Duration = Duration::from_secs(120);
+const SHUTDOWN_POLL: Duration = Duration::from_secs(5);
+
+/// Runs a subcommand without starting the terminal UI.
+pub fn run(args: Args, command: Command) -> Result<()> {
+    match command {
+        Command::Auth(command) => auth(args, command),
+        Command::Servers(command) => servers(args, command),
+        Command::Create(command) => servers(args, ServersCommand::Create(command)),
+        Command::Fleet(command) => fleet(args, command),
+        Command::Status => status(args),
+    }
+}
+
+fn auth(args: Args, command: AuthCommand) -> Result<()> {
+    match command {
+        AuthCommand::Add { name, file } => {
+            // Piped tokens are read as is, so scripts can pass them without argv
+            let token = if std::io::stdin().is_terminal() {
+                rpassword::prompt_password(format!("Token for {}: ", name))?
+            } else {
+                let mut token = String::new();
+                std::io::stdin().read_line(&mut token)?;
+                token
+            };
+            if token.trim().is_empty() {
+                bail!("Token cannot be empty");
+            }
+
+            let backend = credentials::store(&name, token.trim(), file)?;
+            println!("Stored token for {} in the {}", name, backend);
+
+            if !ConfigFile::load(args.config.as_deref())?
+                .profiles
+                .contains_key(&name)
+            {
+                println!("Note: no profile {} is defined in the config file", name);
+            }
+        }
+        AuthCommand::List => {
+            for (name, backend) in credentials::list()? {
+                println!("{}\t{}", name, backend);
+            }
+        }
+        AuthCommand::Remove { name } => {
+            let backend = credentials::remove(&name)?;
+            println!("Removed token for {} from the {}", name, backend);
+        }
+    }
+    Ok(())
+}
+
+/// Connects the configured accounts and runs a single task to completion.
+struct Session {
+    runtime: Runtime,
+    connections: Vec<Connection>,
+    blueprints: BTreeMap<String, Blueprint>,
+}
+
+impl Session {
+    fn new(args: Args) -> Result<Self> {
+        let config = Config::new(args)?;
+        let connections = config
+            .accounts
+            .iter()
+            .map(providers::connect)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            runtime: Runtime::new()?,
+            connections,
+            blueprints: config.blueprints,
+        })
+    }
+
+    fn run(&self, request: Tasks) -> Result<UserEvent> {
+        self.run_on(&self.connections, request)
+    }
+
+    /// Runs a task against some of the accounts only.
+    fn run_on(&self, connections: &[Connection], request: Tasks) -> Result<UserEvent> {
+        // Leaving out an account would read as it having no servers
+        let mut task = Task::new(request);
+        task.strict = true;
+        let span = task.span();
+        let started = Instant::now();
+        let result = self
+            .runtime
+            .block_on(task.run(connections).instrument(span.clone()));
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        span.in_scope(|| match &result {
+            Ok(()) => tracing::info!(elapsed_ms, "Task finished"),
+            Err(err) => tracing::error!(elapsed_ms, "Task failed: {:#}", err),
+        });
+        result?;
+        task.response.ok_or(anyhow!("Task returned no result"))
+    }
+
+    /// Finds a single server by name or id across the connected accounts.
+    fn find(&self, server: &str) -> Result<Server> {
+        let mut matches = self
+            .servers()?
+            .into_iter()
+            .filter(|s| s.name == server || s.id == server)
+            .collect::<Vec<_>>();
+        match matches.len() {
+            0 => bail!("No server {} found", server),
+            1 => Ok(matches.remove(0)),
+            _ => bail!(
+                "Server {} exists on several accounts, select one with --profile",
+                server
+            ),
+        }
+    }
+
+    fn servers(&self) -> Result<Vec<Server>> {
+        self.servers_on(&self.connections)
+    }
+
+    /// Lists the servers of the accounts, failing when any cannot be listed.
+    fn servers_on(&self, connections: &[Connection]) -> Result<Vec<Server>> {
+        match self.run_on(connections, Tasks::FetchServers)? {
+            UserEvent::ServerListStatus(list) => Ok(list
+                .servers
+                .into_iter()
+                .filter_map(|server| match server {
+                    ServerHandle::Server(server) => Some(*server),
+                    ServerHandle::Create => None,
+                })
+                .collect()),
+            event => bail!("Unexpected task result {:?}", event),
+        }
+    }
+
+    /// Shuts a server down and waits until it is off, cutting the power when
+    /// the guest has not stopped in time.
+    fn shut_down(&self, connection: &Connection, server: &Server) -> Result<()> {
+        let connections = std::slice::from_ref(connection);
+        let power = |action| {
+            self.run_on(
+                connections,
+                Tasks::Power(connection.account.clone(), server.id.clone(), action),
+            )
+        };
+
+        power(PowerAction::Shutdown)?;
+        let deadline = Instant::now() + SHUTDOWN_WAIT;
+        while Instant::now() < deadline {
+            let off = self
+                .servers_on(connections)?
+                .iter()
+                .any(|listed| listed.id == server.id && listed.state == ServerState::Off);
+            if off {
+                return Ok(());
+            }
+            std::thread::sleep(SHUTDOWN_POLL);
+        }
+
+        println!(
+            "{} did not shut down within {}s, powering it off",
+            server.name,
+            SHUTDOWN_WAIT.as_secs()
+        );
+        power(PowerAction::PowerOff)?;
+        Ok(())
+    }
+}
+
+fn servers(args: Args, command: ServersCommand) -> Result<()> {
+    let output = args.output;
+    let session = Session::new(args)?;
+
+    match command {
+        ServersCommand::List => {
+            let statuses = session
+                .servers()?
+                .into_iter()
+                .filter_map(|server| ServerHandle::Server(Box::new(server)).to_status())
+                .collect::<Vec<_>>();
+            print_list(output, &statuses)?;
+        }
+        ServersCommand::Show { server } => {
+            let preview = ServerHandle::Server(Box::new(session.find(&server)?)).to_preview();
+            print_item(output, &preview)?;
+        }
+        ServersCommand::Create(command) => create(&session, *command)?,
+        ServersCommand::Delete { server, yes } => {
+            let target = session.find(&server)?;
+
+            if !yes {
+                if !std::io::stdin().is_terminal() {
+                    bail!("Refusing to delete {} without --yes", target.name);
+                }
+                eprint!("Type {} to delete it: ", target.name);
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if answer.trim() != target.name {
+                    bail!("Aborted");
+                }
+            }
+
+            session.run(Tasks::DeleteServer(
+                target.account.clone(),
+                target.id.clone(),
+            ))?;
+            println!("Deleted server {} on {}", target.name, target.account);
+        }
+    }
+    Ok(())
+}
+
+fn create(session: &Session, command: CreateCommand) -> Result<()> {
+    let CreateCommand {
+        name,
+        blueprint,
+        server_type,
+        image,
+        location,
+        datacenter,
+        ssh_keys,
+        ipv4,
+        no_ipv6,
+        primary_ips,
+        networks,
+        firewalls,
+        user_data,
+        labels,
+    } = command;
+
+    let preset = match blueprint.as_ref() {
+        Some(blueprint) => session
+            .blueprints
+            .get(blueprint)
+            .with_context(|| format!("Blueprint {} is not defined", blueprint))?
+            .clone(),
+        None => Blueprint::default(),
+    };
+    let name = match (name, blueprint.as_ref()) {
+        (Some(name), _) => name,
+        (None, Some(blueprint)) => {
+            let servers = session
+                .servers()?
+                .into_iter()
+                .map(|server| server.name)
+                .collect::<Vec<_>>();
+            Blueprint::next_name(blueprint, &servers)
+        }
+        (None, None) => bail!("A server name is required without --blueprint"),
+    };
+    validation::hostname(&name)?;
+
+    let connection = &session.connections[0];
+    let defaults = &connection.defaults;
+    let user_data = user_data
+        .or(preset.user_data.clone())
+        .map(|reference| cloud_init::load(&reference, defaults.templates.as_deref()))
+        .transpose()?;
+    let mut request = CreateServer {
+        name: name.clone(),
+        server_type,
+        image,
+        location,
+        datacenter,
+        ssh_keys,
+        enable_ipv4: ipv4 || preset.ipv4 == Some(true),
+        enable_ipv6: !no_ipv6 && preset.ipv6 != Some(false),
+        primary_ips,
+        networks,
+        firewalls,
+        labels: labels.into_iter().collect(),
+        user_data,
+    };
+    preset.apply(&mut request);
+    defaults.apply(&mut request)?;
+
+    session.run(Tasks::CreateServer(
+        connection.account.clone(),
+        Box::new(request),
+    ))?;
+    println!("Created server {} on {}", name, connection.account);
+    Ok(())
+}
+
+fn fleet(args: Args, command: FleetCommand) -> Result<()> {
+    let (file, apply, yes) = match command {
+        FleetCommand::Plan { file } => (file, false, false),
+        FleetCommand::Apply { file, yes } => (file, true, yes),
+    };
+    let fleet = Fleet::load(&file)?;
+    let session = Session::new(args)?;
+    let connection = &session.connections[0];
+
+    // A missing listing would plan every server as new
+    let servers = session
+        .servers_on(std::slice::from_ref(connection))
+        .with_context(|| format!("Cannot plan fleet {}", fleet.name))?;
+    let mut changes = fleet.plan(&servers);
+    for change in changes.iter_mut() {
+        if let Change::Create(request) | Change::Replace(_, request, _) = change {
+            connection
+                .defaults
+                .apply(request)
+                .with_context(|| format!("Cannot prepare {}", request.name))?;
+        }
+    }
+    if changes.is_empty() {
+        println!(
+            "Fleet {} on {} is up to date",
+            fleet.name, connection.account
+        );
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    let count = |kind: fn(&Change) -> bool| changes.iter().filter(|c| kind(c)).count();
+    println!(
+        "Plan: {} to create, {} to change, {} to replace, {} to delete",
+        count(|c| matches!(c, Change::Create(_))),
+        count(|c| matches!(c, Change::Update(..))),
+        count(|c| matches!(c, Change::Replace(..))),
+        count(|c| matches!(c, Change::Delete(_))),
+    );
+
+    // Nothing is deleted for a replacement that cannot be created
+    if changes
+        .iter()
+        .any(|change| matches!(change, Change::Create(_) | Change::Replace(..)))
+    {
+        let UserEvent::CreateOptions(options) = session.run_on(
+            std::slice::from_ref(connection),
+            Tasks::FetchCreateOptions(connection.account.clone(), Vec::new()),
+        )?
+        else {
+            bail!("Unexpected task result");
+        };
+        let server_types = options
+            .server_types
+            .iter()
+            .map(|server_type| server_type.name.clone())
+            .collect::<Vec<_>>();
+        let errors = fleet::check(&changes, &server_types);
+        if !errors.is_empty() {
+            bail!(
+                "Fleet {} cannot be applied:\n  {}",
+                fleet.name,
+                errors.join("\n  ")
+            );
+        }
+    }
+    if !apply {
+        return Ok(());
+    }
+
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            bail!("Refusing to apply {} changes without --yes", changes.len());
+        }
+        eprint!(
+            "Apply {} changes to {}? [y/N] ",
+            changes.len(),
+            connection.account
+        );
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            bail!("Aborted");
+        }
+    }
+
+    let account = &connection.account;
+    let create =
+        |request: Box<CreateServer>| session.run(Tasks::CreateServer(account.clone(), request));
+    for change in changes {
+        match change {
+            Change::Create(request) => {
+                let name = request.name.clone();
+                create(request)?;
+                println!("Created {}", name);
+            }
+            Change::Update(server, update, _) => {
+                // Server types can only change while the server is off
+                let restart = update.server_type.is_some() && server.state == ServerState::Running;
+                if restart {
+                    session.shut_down(connection, &server)?;
+                }
+                let updated = session.run(Tasks::UpdateServer(
+                    account.clone(),
+                    server.id.clone(),
+                    update,
+                ));
+                if restart {
+                    if let Err(err) = session.run(Tasks::Power(
+                        account.clone(),
+                        server.id.clone(),
+                        PowerAction::PowerOn,
+                    )) {
+                        let err = err.context(format!("{} was left off", server.name));
+                        if updated.is_ok() {
+                            return Err(err);
+                        }
+                        eprintln!("Error: {:#}", err);
+                    }
+                }
+                updated.with_context(|| format!("Cannot change {}", server.name))?;
+                println!("Changed {}", server.name);
+            }
+            Change::Replace(server, request, _) => {
+                session.run(Tasks::DeleteServer(account.clone(), server.id.clone()))?;
+                println!("Deleted {}", server.name);
+                create(request).with_context(|| {
+                    format!("{} was deleted but cannot be created again", server.name)
+                })?;
+                println!("Replaced {}", server.name);
+            }
+            Change::Delete(server) => {
+                session.run(Tasks::DeleteServer(account.clone(), server.id.clone()))?;
+                println!("Deleted {}", server.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn status(args: Args) -> Result<()> {
+    let output = args.output;
+    let session = Session::new(args)?;
+    let UserEvent::ProviderStatus(statuses) = session.run(Tasks::ProviderStatus)? else {
+        bail!("Unexpected task result");
+    };
+
+    print_list(output, &statuses)?;
+
+    // Let scripts and CI notice an unreachable account
+    let disconnected = statuses.iter().filter(|s| !s.is_connected()).count();
+    if disconnected > 0 {
+        bail!(
+            "{} of {} accounts disconnected",
+            disconnected,
+            statuses.len()
+        );
+    }
+    Ok(())
+}
+
+/// Flattens a model into named cells, in field order, for tables and CSV.
+fn cells<T: Serialize>(item: &T) -> Result<Vec<(String, String)>> {
+    let serde_yaml::Value::Mapping(fields) = serde_yaml::to_value(item)? else {
+        bail!("Cannot render a non-struct value as a table");
+    };
+
+    fields
+        .into_iter()
+        .map(|(key, value)| {
+            let key = key.as_str().unwrap_or_default().to_string();
+            let value = match value {
+                serde_yaml::Value::Null => String::new(),
+                serde_yaml::Value::Bool(value) => value.to_string(),
+                serde_yaml::Value::Number(value) => value.to_string(),
+                serde_yaml::Value::String(value) => value,
+                value => serde_json::to_string(&value)?,
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn print_list<T: Serialize>(output: Output, items: &[T]) -> Result<()> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        Output::Yaml => print!("{}", serde_yaml::to_string(items)?),
+        Output::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for (index, item) in items.iter().enumerate() {
+                let (keys, values): (Vec<_>, Vec<_>) = cells(item)?.into_iter().unzip();
+                if index == 0 {
+                    writer.write_record(keys)?;
+                }
+                writer.write_record(values)?;
+            }
+            writer.flush()?;
+        }
+        Output::Table => {
+            let rows = items.iter().map(cells).collect::<Result<Vec<_>>>()?;
+            let Some(first) = rows.first() else {
+                return Ok(());
+            };
+            let headers = first
+                .iter()
+                .map(|(key, _)| key.replace('_', " ").to_uppercase())
+                .collect::<Vec<_>>();
+            let rows = rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|(_, value)| value).collect())
+                .collect::<Vec<_>>();
+            print_table(&headers, &rows);
+        }
+    }
+    Ok(())
+}
+
+fn print_item<T: Serialize>(output: Output, item: &T) -> Result<()> {
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        Output::Yaml => print!("{}", serde_yaml::to_string(item)?),
+        Output::Csv => print_list(output, std::slice::from_ref(item))?,
+        Output::Table => {
+            let cells = cells(item)?;
+            let width = cells.iter().map(|(key, _)| key.len()).max().unwrap_or(0) + 2;
+            for (key, value) in cells {
+                let key = format!("{}:", key.replace('_', " "));
+                println!("{:<width$}{}", key, value, width = width);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_table(headers: &[String], rows: &[Vec<String>]) {
+    let mut widths = headers.iter().map(|h| h.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    line(headers);
+    for row in rows {
+        line(row);
+    }
+}
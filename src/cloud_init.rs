@@ -0,0 +1,211 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::constants::Defaults;
+use crate::validation;
+
+/// A cloud-init user-data file offered when creating servers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub content: String,
+}
+
+/// Lists the templates in `dir`, named after their file without the extension.
+pub fn list(dir: &Path) -> Result<Vec<Template>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("Cannot read templates directory {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        templates.push(Template {
+            name: name.to_string(),
+            content: std::fs::read_to_string(&path)
+                .with_context(|| format!("Cannot read template {}", path.display()))?,
+        });
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Templates offered to an account, including its profile's `user_data` file
+/// when that is a path rather than a template name, and the files of
+/// `blueprints` that load. The form flags a blueprint pick that is missing.
+pub fn offered(defaults: &Defaults, blueprints: &[String]) -> Result<Vec<Template>> {
+    let mut templates = match defaults.templates.as_ref() {
+        Some(dir) => list(dir)?,
+        None => Vec::new(),
+    };
+    if let Some(reference) = defaults.user_data.as_ref() {
+        if !templates.iter().any(|template| &template.name == reference) {
+            templates.push(Template {
+                name: reference.clone(),
+                content: std::fs::read_to_string(reference)
+                    .with_context(|| format!("Cannot read user data {}", reference))?,
+            });
+        }
+    }
+    for reference in blueprints {
+        if templates.iter().any(|template| &template.name == reference) {
+            continue;
+        }
+        match load(reference, None) {
+            Ok(content) => templates.push(Template {
+                name: reference.clone(),
+                content,
+            }),
+            Err(err) => tracing::warn!("Blueprint user data left out: {:#}", err),
+        }
+    }
+    Ok(templates)
+}
+
+/// Reads the template called `reference` from `dir`, or else the file at that
+/// path, and checks it is cloud-init user data.
+pub fn load(reference: &str, dir: Option<&Path>) -> Result<String> {
+    let template = match dir {
+        Some(dir) => list(dir)?
+            .into_iter()
+            .find(|template| template.name == reference),
+        None => None,
+    };
+    let content = match template {
+        Some(template) => template.content,
+        None => std::fs::read_to_string(reference)
+            .with_context(|| format!("{} is neither a template nor a readable file", reference))?,
+    };
+
+    validation::user_data(&content).with_context(|| format!("Invalid user data {}", reference))?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    const WEB: &str = "#cloud-config\npackages: [nginx]\n";
+    const DB: &str = "#cloud-config\npackages: [postgresql]\n";
+
+    /// A templates directory with `web` and `db`, plus a file outside of it.
+    fn templates() -> std::path::PathBuf {
+        let dir = temp_dir();
+        std::fs::create_dir(dir.join("templates")).unwrap();
+        std::fs::create_dir(dir.join("templates").join("nested")).unwrap();
+        std::fs::write(dir.join("templates").join("web.yaml"), WEB).unwrap();
+        std::fs::write(dir.join("templates").join("db.yml"), DB).unwrap();
+        std::fs::write(dir.join("custom.yaml"), DB).unwrap();
+        std::fs::write(dir.join("script.sh"), "#!/bin/sh\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn lists_files_by_name() {
+        let dir = templates();
+        let names = list(&dir.join("templates"))
+            .unwrap()
+            .into_iter()
+            .map(|template| template.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["db", "web"]);
+        assert!(list(&dir.join("missing")).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loads_template_by_name() {
+        let dir = templates();
+        let templates = dir.join("templates");
+        assert_eq!(load("web", Some(&templates)).unwrap(), WEB);
+        assert!(load("web", None).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loads_file_by_path() {
+        let dir = templates();
+        let templates = dir.join("templates");
+        let custom = dir.join("custom.yaml");
+        let custom = custom.to_str().unwrap();
+        assert_eq!(load(custom, Some(&templates)).unwrap(), DB);
+        assert_eq!(load(custom, None).unwrap(), DB);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_or_invalid_user_data() {
+        let dir = templates();
+        let templates = dir.join("templates");
+        assert!(load("missing", Some(&templates)).is_err());
+        let script = dir.join("script.sh");
+        assert!(load(script.to_str().unwrap(), Some(&templates)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn offers_profile_user_data_file() {
+        let dir = templates();
+        let custom = dir.join("custom.yaml").to_str().unwrap().to_string();
+        let defaults = Defaults {
+            user_data: Some(custom.clone()),
+            templates: Some(dir.join("templates")),
+            ..Default::default()
+        };
+        let templates = offered(&defaults, &[]).unwrap();
+        assert_eq!(templates.len(), 3);
+        assert_eq!(
+            templates[2],
+            Template {
+                name: custom,
+                content: DB.to_string(),
+            }
+        );
+
+        let defaults = Defaults {
+            user_data: Some("web".to_string()),
+            ..defaults
+        };
+        assert_eq!(offered(&defaults, &[]).unwrap().len(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn offers_blueprint_user_data_that_loads() {
+        let dir = templates();
+        let defaults = Defaults {
+            templates: Some(dir.join("templates")),
+            ..Default::default()
+        };
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let names = offered(
+            &defaults,
+            &[
+                "web".to_string(),
+                path("custom.yaml"),
+                path("custom.yaml"),
+                path("script.sh"),
+                path("missing.yaml"),
+            ],
+        )
+        .unwrap()
+        .into_iter()
+        .map(|template| template.name)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["db".to_string(), "web".to_string(), path("custom.yaml")]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
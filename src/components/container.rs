@@ -1,5 +1,6 @@
 use tui_realm_stdlib::Container;
 use tuirealm::command::Cmd;
+use tuirealm::event::{Key, KeyEvent};
 use tuirealm::props::{Alignment, Color, Layout, PropPayload, PropValue, TextSpan};
 use tuirealm::tui::layout::{Constraint, Direction};
 use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
@@ -10,6 +11,8 @@ use crate::constants::{Msg, ProviderStatus, ServerPreview, UserEvent, UserEventI
 #[derive(MockComponent)]
 pub struct Header {
     component: Container,
+    statuses: Vec<ProviderStatus>,
+    selected: Option<usize>,
 }
 
 impl Default for Header {
@@ -37,6 +40,8 @@ impl Default for Header {
                             .as_ref(),
                         ),
                 ),
+            statuses: Vec::new(),
+            selected: None,
         };
         obj.update_status(ProviderStatus::default());
         obj
@@ -44,19 +49,81 @@ impl Default for Header {
 }
 
 impl Header {
+    /// Shows the selected account, or the sum of all accounts when none is selected.
+    fn render(&mut self) {
+        let status = match self.selected {
+            Some(index) => self.statuses[index].clone(),
+            None if self.statuses.len() == 1 => self.statuses[0].clone(),
+            None => ProviderStatus::aggregate(&self.statuses),
+        };
+        self.update_status(status);
+    }
+
+    fn connected(&self) -> bool {
+        self.statuses.iter().any(|s| s.is_connected())
+    }
+
+    /// Shows new statuses, true when any account is connected.
+    fn set_statuses(&mut self, statuses: Vec<ProviderStatus>) -> bool {
+        // Keep showing the account picked before a remount or refresh
+        let account = match self.query(Attribute::Custom("account")) {
+            Some(AttrValue::String(account)) => account,
+            _ => String::new(),
+        };
+        self.selected = statuses.iter().position(|s| s.account == account);
+        self.statuses = statuses;
+        self.render();
+        self.connected()
+    }
+
+    /// Moves the selection by `step` through the aggregate view and every account.
+    fn cycle(&mut self, step: isize) -> Option<String> {
+        let views = self.statuses.len() as isize + 1;
+        let current = self.selected.map(|i| i as isize + 1).unwrap_or(0);
+        let next = (current + step).rem_euclid(views);
+        self.selected = if next == 0 {
+            None
+        } else {
+            Some(next as usize - 1)
+        };
+        self.render();
+
+        let account = self.selected.map(|i| self.statuses[i].account.clone());
+        self.attr(
+            Attribute::Custom("account"),
+            AttrValue::String(account.clone().unwrap_or_default()),
+        );
+        account
+    }
+
     pub fn update_status(&mut self, status: ProviderStatus) {
+        let accounts = self.statuses.len();
         let mut children = self.component.children.iter_mut();
         for i in 0..3 {
             let data = match i {
                 0 => vec![
                     TextSpan::new(" Provider: "),
                     TextSpan::new(status.name.clone()).bold(),
+                    TextSpan::new(if status.account.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" ({})", status.account)
+                    }),
                     TextSpan::new(", Status: "),
-                    if status.status == "Connected" {
+                    if status.is_connected() {
                         TextSpan::new(status.status.clone()).fg(Color::LightGreen)
                     } else {
                         TextSpan::new(status.status.clone()).fg(Color::LightYellow)
                     },
+                    match status.quota {
+                        // Warn once less than a tenth of the requests are left
+                        Some(quota) if quota.remaining * 10 < quota.limit => {
+                            TextSpan::new(format!(", Requests left: {}", quota))
+                                .fg(Color::LightYellow)
+                        }
+                        Some(quota) => TextSpan::new(format!(", Requests left: {}", quota)),
+                        None => TextSpan::new(""),
+                    },
                 ],
                 1 => vec![
                     TextSpan::new(" Servers: "),
@@ -84,7 +151,11 @@ impl Header {
                         TextSpan::new(status.load_balancers.to_string()).fg(Color::LightYellow)
                     },
                 ],
-                2 => vec![TextSpan::new("Press ESC to exit.")],
+                2 => vec![TextSpan::new(if accounts > 1 {
+                    "Press ESC to exit, Left/Right to switch account."
+                } else {
+                    "Press ESC to exit."
+                })],
                 _ => vec![],
             };
             if let Some(textbox) = children.next() {
@@ -104,18 +175,34 @@ impl Header {
 impl Component<Msg, UserEventIter> for Header {
     fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
         let cmd = match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Left, ..
+            }) if self.statuses.len() > 1 => return Some(Msg::SelectAccount(self.cycle(-1))),
+            Event::Keyboard(KeyEvent {
+                code: Key::Right, ..
+            }) if self.statuses.len() > 1 => return Some(Msg::SelectAccount(self.cycle(1))),
             Event::User(UserEventIter { events }) => {
                 let mut msg = Msg::Nop(0);
                 for ev in events {
-                    if let UserEvent::ProviderStatus(status) = ev {
-                        msg = if status.status == "Connected" {
-                            Msg::Connected
-                        } else {
-                            Msg::Disconnected
-                        };
-                        self.update_status(status);
-                    } else if let UserEvent::Refresh = ev {
-                        msg = Msg::UpdateProviderStatus;
+                    match ev {
+                        UserEvent::ProviderStatus(statuses) => {
+                            msg = match self.set_statuses(statuses) {
+                                true => Msg::Connected,
+                                false => Msg::Disconnected,
+                            };
+                        }
+                        // Background refreshes only report a change of connection
+                        UserEvent::Polled(statuses, _) => {
+                            let connected = self.connected();
+                            if self.set_statuses(statuses) != connected {
+                                msg = match connected {
+                                    false => Msg::Connected,
+                                    true => Msg::Disconnected,
+                                };
+                            }
+                        }
+                        UserEvent::Refresh => msg = Msg::UpdateProviderStatus,
+                        _ => {}
                     }
                 }
                 return Some(msg);
@@ -176,6 +263,10 @@ impl Preview {
                         TextSpan::new("   Provider: "),
                         TextSpan::new(server.provider),
                     ])),
+                    Box::new(TextBox::new(&[
+                        TextSpan::new("   Account: "),
+                        TextSpan::new(server.account),
+                    ])),
                     Box::new(TextBox::new(&[
                         TextSpan::new("   Created On: "),
                         TextSpan::new(server.created_on),
@@ -7,6 +7,11 @@ use tuirealm::props::{Alignment, BorderType, Borders, Color, InputType, Style};
 use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
 
 use crate::constants::{InputId, Msg, UserEventIter};
+use crate::validation;
+
+/// Input type that shows the invalid style whatever the value, set on fields
+/// the form rejected until they are edited.
+pub const INVALID: InputType = InputType::Custom(|_| false, |_, _| true);
 
 #[derive(MockComponent)]
 pub struct TextInput {
@@ -34,7 +39,6 @@ impl Default for TextInput {
 impl TextInput {
     pub fn new(id: InputId, title: &str) -> Self {
         Self {
-            id: Some(id),
             component: Input::default()
                 .borders(
                     Borders::default()
@@ -45,6 +49,17 @@ impl TextInput {
                 .foreground(Color::LightYellow)
                 .input_type(InputType::Text)
                 .invalid_style(Style::default().fg(Color::Red)),
+            id: Some(id),
+        }
+    }
+
+    /// Server names are checked while typing, an empty field is not flagged yet.
+    fn input_type(id: &InputId, value: &str) -> InputType {
+        match id {
+            InputId::CreateServerName if !value.is_empty() && !validation::is_hostname(value) => {
+                INVALID
+            }
+            _ => InputType::Text,
         }
     }
 
@@ -102,13 +117,19 @@ impl Component<Msg, UserEventIter> for TextInput {
         };
 
         match self.perform(cmd) {
-            CmdResult::Changed(state) => {
-                let val = state.unwrap_one().unwrap_string();
+            CmdResult::Changed(_) => {
+                // The stdlib input hides invalid values, so read it as plain text
+                self.attr(Attribute::InputType, AttrValue::InputType(InputType::Text));
+                let val = self.component.state().unwrap_one().unwrap_string();
+                if let Some(id) = self.id.as_ref() {
+                    let input_type = Self::input_type(id, &val);
+                    self.attr(Attribute::InputType, AttrValue::InputType(input_type));
+                }
                 self.attr(Attribute::Custom("state"), AttrValue::String(val.clone()));
                 Some(Msg::Input(self.id.clone().unwrap(), val))
             }
             CmdResult::Submit(_) => match self.id.clone().unwrap() {
-                InputId::CreateServerImage => Some(Msg::Submit),
+                InputId::CreateServerName => Some(Msg::ChangeFocus()),
                 _ => None,
             },
             _ => None,
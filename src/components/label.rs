@@ -1,7 +1,6 @@
 use tui_realm_stdlib::Label;
-use tuirealm::command::Cmd;
-use tuirealm::props::{Alignment, Color, TextModifiers, TextSpan};
-use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
+use tuirealm::props::{Alignment, Color, TextModifiers};
+use tuirealm::{Component, Event, MockComponent};
 
 use crate::constants::{Msg, UserEvent, UserEventIter};
 
@@ -25,18 +24,14 @@ impl Default for TextLabel {
 
 impl Component<Msg, UserEventIter> for TextLabel {
     fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
-        let _ = match ev {
-            Event::User(UserEventIter { events }) => {
-                for ev in events {
-                    if let UserEvent::Error(err) = ev {
-                        self.attr(Attribute::Text, AttrValue::Text(TextSpan::new(err)));
-                    }
-                }
-                Cmd::None
-            }
-            _ => Cmd::None,
+        let Event::User(UserEventIter { events }) = ev else {
+            return None;
         };
-
-        None
+        // The model writes the label, so every report ends up in the history
+        let reported = events
+            .into_iter()
+            .filter(|ev| matches!(ev, UserEvent::Error(_) | UserEvent::Notice(..)))
+            .collect::<Vec<_>>();
+        (!reported.is_empty()).then_some(Msg::Reported(reported))
     }
 }
@@ -1,7 +1,9 @@
 pub mod container;
 pub mod input;
 pub mod label;
+pub mod modal;
 pub mod paragraph;
 pub mod phantom;
+pub mod picker;
 pub mod span;
 pub mod table;
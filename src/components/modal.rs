@@ -0,0 +1,144 @@
+use tui_realm_stdlib::Input;
+use tuirealm::command::{Cmd, CmdResult, Direction, Position};
+use tuirealm::event::{Key, KeyEvent, KeyModifiers};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, InputType};
+use tuirealm::{Component, Event, MockComponent};
+
+use crate::constants::{Msg, Prompt, UserEventIter};
+
+/// Asks for the server name, or a phrase for several servers, to be typed
+/// back before deleting.
+#[derive(MockComponent)]
+pub struct DeleteModal {
+    component: Input,
+}
+
+impl DeleteModal {
+    pub fn new(confirm: &str, subject: &str) -> Self {
+        Self {
+            component: Input::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::Red),
+                )
+                .title(
+                    format!(
+                        " Type \"{}\" to delete {}, Esc to cancel ",
+                        confirm, subject
+                    ),
+                    Alignment::Center,
+                )
+                .foreground(Color::LightRed)
+                .input_type(InputType::Text),
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for DeleteModal {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::CloseModal),
+            Event::Keyboard(KeyEvent {
+                code: Key::Left, ..
+            }) => Cmd::Move(Direction::Left),
+            Event::Keyboard(KeyEvent {
+                code: Key::Right, ..
+            }) => Cmd::Move(Direction::Right),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::Keyboard(KeyEvent {
+                code: Key::Backspace,
+                ..
+            }) => Cmd::Delete,
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => Cmd::Submit,
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            }) => Cmd::Type(ch),
+            _ => Cmd::None,
+        };
+
+        match self.perform(cmd) {
+            CmdResult::Submit(state) => {
+                Some(Msg::ConfirmDelete(state.unwrap_one().unwrap_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Asks a single line question, Enter answers and Esc cancels.
+#[derive(MockComponent)]
+pub struct PromptModal {
+    component: Input,
+    prompt: Prompt,
+}
+
+impl PromptModal {
+    pub fn new(prompt: Prompt) -> Self {
+        let title = match prompt {
+            Prompt::Mark => {
+                " Mark servers matching (name, status, account or key=value, empty for all) "
+            }
+            Prompt::Label => " Label to add to the servers as key=value, Esc to cancel ",
+        };
+        Self {
+            component: Input::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::LightYellow),
+                )
+                .title(title, Alignment::Center)
+                .foreground(Color::LightYellow)
+                .input_type(InputType::Text),
+            prompt,
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for PromptModal {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::CloseModal),
+            Event::Keyboard(KeyEvent {
+                code: Key::Left, ..
+            }) => Cmd::Move(Direction::Left),
+            Event::Keyboard(KeyEvent {
+                code: Key::Right, ..
+            }) => Cmd::Move(Direction::Right),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::Keyboard(KeyEvent {
+                code: Key::Backspace,
+                ..
+            }) => Cmd::Delete,
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => Cmd::Submit,
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+            }) => Cmd::Type(ch),
+            _ => Cmd::None,
+        };
+
+        match self.perform(cmd) {
+            CmdResult::Submit(state) => {
+                let answer = state.unwrap_one().unwrap_string();
+                Some(match self.prompt {
+                    Prompt::Mark => Msg::Mark(answer),
+                    Prompt::Label => Msg::ConfirmLabel(answer),
+                })
+            }
+            _ => None,
+        }
+    }
+}
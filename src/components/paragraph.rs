@@ -1,8 +1,13 @@
-use tui_realm_stdlib::Paragraph;
-use tuirealm::props::{Alignment, Color, TextSpan};
-use tuirealm::{Component, Event, MockComponent};
+use tui_realm_stdlib::{Paragraph, Textarea};
+use tuirealm::command::{Cmd, Direction, Position};
+use tuirealm::event::{Key, KeyEvent};
+use tuirealm::props::{Alignment, BorderType, Borders, Color, PropPayload, PropValue, TextSpan};
+use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
 
-use crate::constants::{Msg, UserEventIter};
+use crate::app::notifications::{Notification, Severity};
+use crate::constants::{Msg, UserEvent, UserEventIter};
+use crate::error::ErrorReport;
+use crate::{logging, validation};
 
 #[derive(MockComponent)]
 pub struct ServerListDisconnected {
@@ -30,3 +35,345 @@ impl Component<Msg, UserEventIter> for ServerListDisconnected {
         None
     }
 }
+
+/// Shows the cloud-init user data picked on the create screen.
+#[derive(MockComponent)]
+pub struct UserDataPreview {
+    component: Paragraph,
+}
+
+impl Default for UserDataPreview {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl UserDataPreview {
+    pub fn new(content: &str) -> Self {
+        let mut text = Vec::new();
+        if content.is_empty() {
+            text.push(TextSpan::new("No user data, the image boots as is."));
+        } else if let Err(err) = validation::user_data(content) {
+            text.push(TextSpan::new(format!("{:#}", err)).fg(Color::Red));
+        }
+        text.extend(content.lines().map(TextSpan::new));
+
+        Self {
+            component: Paragraph::default()
+                .background(Color::Reset)
+                .foreground(Color::LightYellow)
+                .title(" User data preview ", Alignment::Left)
+                .text(&text),
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for UserDataPreview {
+    fn on(&mut self, _: Event<UserEventIter>) -> Option<Msg> {
+        None
+    }
+}
+
+/// Lists the servers of a batch action with their outcome as it comes in.
+#[derive(MockComponent)]
+pub struct BatchProgress {
+    component: Paragraph,
+    title: String,
+    items: Vec<(String, Option<Option<String>>)>,
+}
+
+impl BatchProgress {
+    pub fn new(title: &str, servers: Vec<String>) -> Self {
+        let mut progress = Self {
+            component: Paragraph::default()
+                .background(Color::Reset)
+                .foreground(Color::LightYellow),
+            title: title.to_string(),
+            items: servers.into_iter().map(|server| (server, None)).collect(),
+        };
+        progress.render();
+        progress
+    }
+
+    fn done(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|(_, result)| result.is_some())
+            .count()
+    }
+
+    fn render(&mut self) {
+        let text = self
+            .items
+            .iter()
+            .map(|(server, result)| match result {
+                None => TextSpan::new(format!("...   {}", server)),
+                Some(None) => TextSpan::new(format!("ok    {}", server)).fg(Color::Green),
+                Some(Some(err)) => {
+                    TextSpan::new(format!("fail  {}: {}", server, err)).fg(Color::Red)
+                }
+            })
+            .collect::<Vec<_>>();
+        let title = format!(
+            " {}: {}/{} done (x: close) ",
+            self.title,
+            self.done(),
+            self.items.len()
+        );
+        self.component
+            .attr(Attribute::Title, AttrValue::Title((title, Alignment::Left)));
+        self.component.attr(
+            Attribute::Text,
+            AttrValue::Payload(PropPayload::Vec(
+                text.into_iter().map(PropValue::TextSpan).collect(),
+            )),
+        );
+    }
+}
+
+impl Component<Msg, UserEventIter> for BatchProgress {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let Event::User(UserEventIter { events }) = ev else {
+            return None;
+        };
+        let before = self.done();
+        for ev in events {
+            if let UserEvent::BatchProgress(server, err) = ev {
+                if let Some((_, result)) = self
+                    .items
+                    .iter_mut()
+                    .find(|(name, result)| *name == server && result.is_none())
+                {
+                    *result = Some(err);
+                }
+            }
+        }
+        if self.done() == before {
+            return None;
+        }
+        self.render();
+
+        if self.done() < self.items.len() {
+            return Some(Msg::Nop(0));
+        }
+        let failed = self
+            .items
+            .iter()
+            .filter(|(_, result)| matches!(result, Some(Some(_))))
+            .count();
+        Some(Msg::Notify(
+            match failed {
+                0 => Severity::Success,
+                _ => Severity::Warning,
+            },
+            format!(
+                "{} finished: {} succeeded, {} failed",
+                self.title,
+                self.items.len() - failed,
+                failed
+            ),
+        ))
+    }
+}
+
+/// Explains the last failed task, with a suggested fix and the raw error chain.
+#[derive(MockComponent)]
+pub struct ErrorPane {
+    component: Textarea,
+}
+
+impl ErrorPane {
+    pub fn new(report: &ErrorReport) -> Self {
+        let mut rows = vec![
+            TextSpan::new(&report.context).bold(),
+            TextSpan::new(report.error.message()).fg(Color::LightRed),
+            TextSpan::new(""),
+            TextSpan::new(format!("Suggested fix: {}", report.error.hint())).fg(Color::LightGreen),
+            TextSpan::new(""),
+            TextSpan::new("Details:").bold(),
+        ];
+        rows.extend(report.details.lines().map(TextSpan::new));
+
+        Self {
+            component: Textarea::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::LightRed),
+                )
+                .foreground(Color::LightYellow)
+                .title(
+                    format!(" {} (Esc/Ctrl+e: close) ", report.error.title()),
+                    Alignment::Left,
+                )
+                .step(4)
+                .text_rows(&rows),
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for ErrorPane {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::CloseError),
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            _ => return None,
+        };
+
+        // The textarea reports nothing back, redraw after every move
+        self.perform(cmd);
+        Some(Msg::Nop(0))
+    }
+}
+
+/// Warnings, errors and successes, shown above the label until they expire
+/// or are dismissed.
+#[derive(MockComponent)]
+pub struct Toasts {
+    component: Paragraph,
+    /// Whether any toast runs out on its own
+    expiring: bool,
+}
+
+impl Toasts {
+    pub fn new(toasts: &[Notification]) -> Self {
+        let errors = toasts.iter().any(|toast| toast.severity == Severity::Error);
+        let text = toasts
+            .iter()
+            .map(|toast| {
+                TextSpan::new(format!("{} {}", toast.clock(), toast.message))
+                    .fg(toast.severity.color())
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            component: Paragraph::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(match errors {
+                            true => Color::LightRed,
+                            false => Color::LightYellow,
+                        }),
+                )
+                .title(" Ctrl+k: dismiss, Ctrl+n: history ", Alignment::Right)
+                .text(&text),
+            expiring: toasts.iter().any(|toast| toast.severity != Severity::Error),
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for Toasts {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        match ev {
+            Event::Tick if self.expiring => Some(Msg::ExpireToasts),
+            _ => None,
+        }
+    }
+}
+
+/// The latest log lines, following new ones until scrolled up.
+#[derive(MockComponent)]
+pub struct DebugLog {
+    component: Textarea,
+    /// Log writes already shown
+    shown: usize,
+    follow: bool,
+}
+
+impl Default for DebugLog {
+    fn default() -> Self {
+        let dir = logging::log_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_default();
+        let mut log = Self {
+            component: Textarea::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::LightMagenta),
+                )
+                .title(
+                    format!(" Debug log in {} (Esc/Ctrl+g: close) ", dir),
+                    Alignment::Left,
+                )
+                .step(4),
+            shown: 0,
+            follow: true,
+        };
+        log.refresh();
+        log
+    }
+}
+
+impl DebugLog {
+    fn refresh(&mut self) {
+        self.shown = logging::writes();
+        let rows = logging::recent()
+            .into_iter()
+            .map(|line| {
+                let color = match line.split_whitespace().nth(1) {
+                    Some("ERROR") => Color::LightRed,
+                    Some("WARN") => Color::Yellow,
+                    Some("DEBUG") | Some("TRACE") => Color::Gray,
+                    _ => Color::Reset,
+                };
+                PropValue::TextSpan(TextSpan::new(line).fg(color))
+            })
+            .collect();
+        self.component
+            .attr(Attribute::Text, AttrValue::Payload(PropPayload::Vec(rows)));
+        self.component.perform(Cmd::GoTo(Position::End));
+    }
+}
+
+impl Component<Msg, UserEventIter> for DebugLog {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::CloseDebug),
+            Event::Tick if self.follow && logging::writes() != self.shown => {
+                self.refresh();
+                return Some(Msg::Nop(0));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => {
+                self.follow = true;
+                self.refresh();
+                return Some(Msg::Nop(0));
+            }
+            _ => return None,
+        };
+
+        // Scrolling back holds the view still, End follows again
+        self.follow = false;
+        self.perform(cmd);
+        Some(Msg::Nop(0))
+    }
+}
@@ -24,19 +24,74 @@ impl PhantomHandler {
                             code: Key::Esc,
                             modifiers: KeyModifiers::NONE
                         }),
-                        SubClause::Always
+                        // The open modal handles Esc itself
+                        Self::no_modal()
                     ),
                     Sub::new(
                         SubEventClause::Keyboard(KeyEvent {
                             code: Key::Tab,
                             modifiers: KeyModifiers::NONE
                         }),
+                        Self::no_modal()
+                    ),
+                    Sub::new(
+                        SubEventClause::Keyboard(KeyEvent {
+                            code: Key::Char('t'),
+                            modifiers: KeyModifiers::CONTROL
+                        }),
+                        SubClause::Always
+                    ),
+                    Sub::new(
+                        SubEventClause::Keyboard(KeyEvent {
+                            code: Key::Char('e'),
+                            modifiers: KeyModifiers::CONTROL
+                        }),
+                        SubClause::Always
+                    ),
+                    Sub::new(
+                        SubEventClause::Keyboard(KeyEvent {
+                            code: Key::Char('n'),
+                            modifiers: KeyModifiers::CONTROL
+                        }),
+                        SubClause::Always
+                    ),
+                    Sub::new(
+                        SubEventClause::Keyboard(KeyEvent {
+                            code: Key::Char('k'),
+                            modifiers: KeyModifiers::CONTROL
+                        }),
+                        SubClause::Always
+                    ),
+                    Sub::new(
+                        SubEventClause::Keyboard(KeyEvent {
+                            code: Key::Char('g'),
+                            modifiers: KeyModifiers::CONTROL
+                        }),
                         SubClause::Always
                     )
                 ]
             )
             .is_ok());
     }
+
+    fn no_modal() -> SubClause<Id> {
+        SubClause::not(SubClause::or(
+            SubClause::or(
+                SubClause::IsMounted(Id::DeleteModal),
+                SubClause::IsMounted(Id::Prompt),
+            ),
+            SubClause::or(
+                SubClause::or(
+                    SubClause::IsMounted(Id::TaskPanel),
+                    SubClause::IsMounted(Id::NotificationPanel),
+                ),
+                SubClause::or(
+                    SubClause::IsMounted(Id::ErrorPane),
+                    SubClause::IsMounted(Id::DebugLog),
+                ),
+            ),
+        ))
+    }
 }
 
 impl Component<Msg, UserEventIter> for PhantomHandler {
@@ -44,6 +99,26 @@ impl Component<Msg, UserEventIter> for PhantomHandler {
         let cmd = match ev {
             Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::AppClose),
             Event::Keyboard(KeyEvent { code: Key::Tab, .. }) => return Some(Msg::ChangeFocus()),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('t'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Some(Msg::ToggleTasks),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('e'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Some(Msg::ToggleError),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('n'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Some(Msg::ToggleNotifications),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('k'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Some(Msg::DismissToasts),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('g'),
+                modifiers: KeyModifiers::CONTROL,
+            }) => return Some(Msg::ToggleDebug),
             _ => Cmd::None,
         };
 
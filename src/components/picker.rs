@@ -0,0 +1,507 @@
+use std::collections::BTreeSet;
+
+use tui_realm_stdlib::List;
+use tuirealm::command::{Cmd, CmdResult, Direction, Position};
+use tuirealm::event::{Key, KeyEvent};
+use tuirealm::props::{
+    Alignment, BorderType, Borders, Color, PropPayload, PropValue, Style, TableBuilder, TextSpan,
+};
+use tuirealm::tui::layout::Rect;
+use tuirealm::{AttrValue, Attribute, Component, Event, Frame, MockComponent, State};
+
+use crate::constants::{CreateOptions, InputId, Msg, UserEvent, UserEventIter};
+use crate::providers::{Resource, ServerType};
+
+/// Selectable list of what the provider offers for one create field.
+///
+/// SSH keys, public IPs, networks and firewalls allow several entries to be
+/// chosen with Space, the other fields pick the highlighted row. The choice is
+/// kept in the `Custom("state")` attr and everything offered in `Custom("options")`.
+///
+/// Setting `Custom("options")` offers the given values instead of provider
+/// data, setting `Custom("pick")` selects entries by value, or by name for user
+/// data, until it is set to `PropPayload::None` to go back to the defaults. A
+/// pick missing from the loaded options flags the field.
+/// Setting `Custom("location")` on the server type picker only offers the
+/// types of that location. `Custom("invalid")` flags the field in red until
+/// the choice changes.
+pub struct Picker {
+    component: List,
+    id: InputId,
+    values: Vec<String>,
+    labels: Vec<String>,
+    chosen: BTreeSet<usize>,
+    defaults: (usize, BTreeSet<usize>),
+    pick: Option<AttrValue>,
+    server_types: Vec<ServerType>,
+    /// Location the server types are offered for, empty for all of them
+    location: String,
+    invalid: bool,
+}
+
+impl Picker {
+    pub fn new(id: InputId, title: &str) -> Self {
+        let mut obj = Self {
+            component: List::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::LightYellow),
+                )
+                .title(title, Alignment::Left)
+                .foreground(Color::LightYellow)
+                .highlighted_color(Color::LightYellow)
+                .highlighted_str(">")
+                .scroll(true)
+                .rewind(true)
+                .step(8),
+            id,
+            values: Vec::new(),
+            labels: Vec::new(),
+            chosen: BTreeSet::new(),
+            defaults: (0, BTreeSet::new()),
+            pick: None,
+            server_types: Vec::new(),
+            location: String::new(),
+            invalid: false,
+        };
+        obj.render(&["Loading...".to_string()]);
+        obj
+    }
+
+    fn multi(&self) -> bool {
+        matches!(
+            self.id,
+            InputId::CreateServerSshKeys
+                | InputId::CreateServerPublicNet
+                | InputId::CreateServerNetworks
+                | InputId::CreateServerFirewalls
+        )
+    }
+
+    fn load(&mut self, options: &CreateOptions) {
+        // Offered by the model, not the provider
+        if self.id == InputId::CreateServerBlueprint {
+            return;
+        }
+
+        let defaults = &options.defaults;
+        let (entries, default): (Vec<(String, String)>, Option<&String>) = match self.id {
+            InputId::CreateServerType => {
+                self.server_types = options.server_types.clone();
+                (self.server_types(), defaults.server_type.as_ref())
+            }
+            // An empty image boots the provider default
+            InputId::CreateServerImage => (
+                std::iter::once((String::new(), "Provider default".to_string()))
+                    .chain(
+                        options
+                            .images
+                            .iter()
+                            .map(|i| (i.name.clone(), format!("{:<24} {}", i.name, i.description))),
+                    )
+                    .collect(),
+                defaults.image.as_ref(),
+            ),
+            InputId::CreateServerLocation => (
+                options
+                    .locations
+                    .iter()
+                    .map(|l| (l.name.clone(), format!("{:<16} {}", l.name, l.description)))
+                    .collect(),
+                defaults.location.as_ref(),
+            ),
+            // An empty datacenter lets the provider pick one in the location
+            InputId::CreateServerDatacenter => (
+                std::iter::once((String::new(), "Any".to_string()))
+                    .chain(options.datacenters.iter().map(resource))
+                    .collect(),
+                None,
+            ),
+            InputId::CreateServerSshKeys => (
+                options
+                    .ssh_keys
+                    .iter()
+                    .map(|key| (key.clone(), key.clone()))
+                    .collect(),
+                None,
+            ),
+            InputId::CreateServerPublicNet => (
+                [
+                    ("ipv4".to_string(), "New IPv4".to_string()),
+                    ("ipv6".to_string(), "New IPv6".to_string()),
+                ]
+                .into_iter()
+                .chain(options.primary_ips.iter().map(resource))
+                .collect(),
+                None,
+            ),
+            InputId::CreateServerNetworks => {
+                (options.networks.iter().map(resource).collect(), None)
+            }
+            InputId::CreateServerFirewalls => {
+                (options.firewalls.iter().map(resource).collect(), None)
+            }
+            // Holds the content itself, the form sends it as is
+            InputId::CreateServerUserData => (
+                std::iter::once((String::new(), "None".to_string()))
+                    .chain(
+                        options
+                            .templates
+                            .iter()
+                            .map(|t| (t.content.clone(), t.name.clone())),
+                    )
+                    .collect(),
+                defaults.user_data.as_ref().and_then(|name| {
+                    options
+                        .templates
+                        .iter()
+                        .find(|t| &t.name == name)
+                        .map(|t| &t.content)
+                }),
+            ),
+            _ => (Vec::new(), None),
+        };
+        (self.values, self.labels) = entries.into_iter().unzip();
+
+        let chosen = match self.id {
+            // Start from the profile defaults, or every key as carton always did
+            InputId::CreateServerSshKeys if defaults.ssh_keys.is_empty() => {
+                (0..self.values.len()).collect()
+            }
+            InputId::CreateServerSshKeys => (0..self.values.len())
+                .filter(|&i| defaults.ssh_keys.contains(&self.values[i]))
+                .collect(),
+            // Servers get a fresh IPv6 address unless told otherwise
+            InputId::CreateServerPublicNet => (0..self.values.len())
+                .filter(|&i| self.values[i] == "ipv6")
+                .collect(),
+            _ => BTreeSet::new(),
+        };
+        let selected = default
+            .and_then(|default| self.values.iter().position(|v| v == default))
+            .unwrap_or(0);
+        self.defaults = (selected, chosen);
+        self.show();
+    }
+
+    /// Server types offered in the location, all of them without one.
+    fn server_types(&self) -> Vec<(String, String)> {
+        self.server_types
+            .iter()
+            .filter(|t| {
+                self.location.is_empty()
+                    || t.locations.is_empty()
+                    || t.locations.contains(&self.location)
+            })
+            .map(|t| {
+                let label = format!(
+                    "{:<8} {:>2} vCPU {:>3} GB {:>4} GB  {}",
+                    t.name,
+                    t.cores,
+                    t.memory,
+                    t.disk,
+                    t.price_monthly
+                        .as_ref()
+                        .map(|price| format!("{}/mo", price))
+                        .unwrap_or_default()
+                );
+                (t.name.clone(), label)
+            })
+            .collect()
+    }
+
+    /// Offers the server types of another location, keeping the selected one
+    /// when it is offered there too.
+    fn relocate(&mut self, location: String) {
+        if self.id != InputId::CreateServerType || location == self.location {
+            return;
+        }
+        self.location = location;
+        let selected = self.values.get(self.selected()).cloned();
+        (self.values, self.labels) = self.server_types().into_iter().unzip();
+        self.defaults.0 = selected
+            .and_then(|selected| self.values.iter().position(|value| *value == selected))
+            .unwrap_or(0);
+        self.show();
+    }
+
+    /// Offers the values set by the model, an empty value reads as "None".
+    fn offer(&mut self, value: AttrValue) {
+        let AttrValue::Payload(PropPayload::Vec(values)) = value else {
+            return;
+        };
+        self.values = values.into_iter().map(|value| value.unwrap_str()).collect();
+        self.labels = self
+            .values
+            .iter()
+            .map(|value| match value.is_empty() {
+                true => "None".to_string(),
+                false => value.clone(),
+            })
+            .collect();
+        self.defaults = (0, BTreeSet::new());
+        self.show();
+    }
+
+    /// Publishes the offered values and selects the defaults, or the pick.
+    fn show(&mut self) {
+        self.component.attr(
+            Attribute::Custom("options"),
+            AttrValue::Payload(PropPayload::Vec(
+                self.values.iter().cloned().map(PropValue::Str).collect(),
+            )),
+        );
+
+        let (selected, chosen) = self.defaults.clone();
+        self.chosen = chosen;
+        if self.values.is_empty() {
+            self.render(&["Nothing available".to_string()]);
+        } else {
+            self.render(&self.labels.clone());
+        }
+        self.component.attr(
+            Attribute::Value,
+            AttrValue::Payload(PropPayload::One(PropValue::Usize(selected))),
+        );
+        self.apply_pick();
+        self.update_state();
+    }
+
+    fn apply_pick(&mut self) {
+        let matches = |index: usize, wanted: &str| {
+            self.values[index] == wanted || self.labels[index] == wanted
+        };
+        match self.pick.clone() {
+            Some(AttrValue::String(wanted)) if !self.multi() => {
+                match (0..self.values.len()).find(|&i| matches(i, &wanted)) {
+                    Some(index) => {
+                        self.component.attr(
+                            Attribute::Value,
+                            AttrValue::Payload(PropPayload::One(PropValue::Usize(index))),
+                        );
+                        self.flag(false);
+                    }
+                    // Options are still loading while there are none
+                    None => self.flag(!self.values.is_empty()),
+                }
+            }
+            Some(AttrValue::Payload(PropPayload::Vec(wanted))) if self.multi() => {
+                let wanted = wanted
+                    .into_iter()
+                    .map(|value| value.unwrap_str())
+                    .collect::<Vec<_>>();
+                self.chosen = (0..self.values.len())
+                    .filter(|&i| wanted.iter().any(|value| matches(i, value)))
+                    .collect();
+                self.render(&self.labels.clone());
+            }
+            None => self.flag(false),
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, labels: &[String]) {
+        let mut table = TableBuilder::default();
+        for (index, label) in labels.iter().enumerate() {
+            let label = if self.multi() && !self.values.is_empty() {
+                let mark = if self.chosen.contains(&index) {
+                    "x"
+                } else {
+                    " "
+                };
+                format!("[{}] {}", mark, label)
+            } else {
+                label.clone()
+            };
+            table.add_col(TextSpan::new(label)).add_row();
+        }
+        self.component
+            .attr(Attribute::Content, AttrValue::Table(table.build()));
+    }
+
+    fn selected(&self) -> usize {
+        self.component.state().unwrap_one().unwrap_usize()
+    }
+
+    fn update_state(&mut self) {
+        let state = if self.multi() {
+            AttrValue::Payload(PropPayload::Vec(
+                self.chosen
+                    .iter()
+                    .map(|&i| PropValue::Str(self.values[i].clone()))
+                    .collect(),
+            ))
+        } else {
+            AttrValue::String(
+                self.values
+                    .get(self.selected())
+                    .cloned()
+                    .unwrap_or_default(),
+            )
+        };
+        self.component.attr(Attribute::Custom("state"), state);
+    }
+
+    /// Lets the model preview the picked user data, apply blueprints and
+    /// offer the server types of the picked location.
+    fn changed(&self) -> Option<Msg> {
+        if !matches!(
+            self.id,
+            InputId::CreateServerUserData
+                | InputId::CreateServerBlueprint
+                | InputId::CreateServerLocation
+        ) {
+            return None;
+        }
+        match self.query(Attribute::Custom("state")) {
+            Some(AttrValue::String(content)) => Some(Msg::Input(self.id.clone(), content)),
+            _ => None,
+        }
+    }
+
+    fn flag(&mut self, invalid: bool) {
+        if invalid == self.invalid {
+            return;
+        }
+        self.invalid = invalid;
+        let (focused, unfocused) = match invalid {
+            true => (Color::Red, Color::Red),
+            false => (Color::LightYellow, Color::Reset),
+        };
+        self.component.attr(
+            Attribute::Borders,
+            AttrValue::Borders(
+                Borders::default()
+                    .modifiers(BorderType::Rounded)
+                    .color(focused),
+            ),
+        );
+        // Lists draw the border of an unfocused field in the focus style
+        self.component.attr(
+            Attribute::FocusStyle,
+            AttrValue::Style(Style::default().fg(unfocused)),
+        );
+    }
+
+    fn toggle(&mut self) {
+        let selected = self.selected();
+        if selected >= self.values.len() {
+            return;
+        }
+        if !self.chosen.remove(&selected) {
+            self.chosen.insert(selected);
+        }
+        self.render(&self.labels.clone());
+        self.update_state();
+    }
+}
+
+impl MockComponent for Picker {
+    fn view(&mut self, frame: &mut Frame, area: Rect) {
+        self.component.view(frame, area);
+    }
+
+    fn query(&self, attr: Attribute) -> Option<AttrValue> {
+        match attr {
+            Attribute::Custom("invalid") => Some(AttrValue::Flag(self.invalid)),
+            attr => self.component.query(attr),
+        }
+    }
+
+    fn attr(&mut self, attr: Attribute, value: AttrValue) {
+        match attr {
+            Attribute::Custom("options") => self.offer(value),
+            Attribute::Custom("invalid") => self.flag(value == AttrValue::Flag(true)),
+            Attribute::Custom("location") => {
+                if let AttrValue::String(location) = value {
+                    self.relocate(location);
+                }
+            }
+            Attribute::Custom("pick") => {
+                self.pick = match value {
+                    AttrValue::Payload(PropPayload::None) => None,
+                    value => Some(value),
+                };
+                self.show();
+            }
+            attr => self.component.attr(attr, value),
+        }
+    }
+
+    fn state(&self) -> State {
+        self.component.state()
+    }
+
+    fn perform(&mut self, cmd: Cmd) -> CmdResult {
+        self.component.perform(cmd)
+    }
+}
+
+impl Component<Msg, UserEventIter> for Picker {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(' '),
+                ..
+            }) if self.multi() => {
+                self.toggle();
+                self.flag(false);
+                return Some(Msg::Nop(0));
+            }
+            // Enter confirms the field, the last step submits the form
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) if self.id == InputId::CreateServerUserData => return Some(Msg::Submit),
+            Event::Keyboard(KeyEvent {
+                code: Key::Enter, ..
+            }) => return Some(Msg::ChangeFocus()),
+            Event::User(UserEventIter { events }) => {
+                for ev in events {
+                    if let UserEvent::CreateOptions(options) = ev {
+                        self.load(&options);
+                    }
+                }
+                // Reloading keeps the blueprint, applying it again would undo edits
+                return match self.id {
+                    InputId::CreateServerBlueprint => Some(Msg::Nop(0)),
+                    _ => Some(self.changed().unwrap_or(Msg::Nop(0))),
+                };
+            }
+            _ => Cmd::None,
+        };
+
+        let result = self.perform(cmd);
+        self.update_state();
+        match result {
+            CmdResult::Changed(_) => {
+                self.flag(false);
+                self.changed()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Picks a resource by id and shows it by name.
+fn resource(resource: &Resource) -> (String, String) {
+    (
+        resource.id.clone(),
+        format!("{:<16} {}", resource.name, resource.description),
+    )
+}
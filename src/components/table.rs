@@ -1,15 +1,27 @@
+use std::collections::BTreeSet;
+
 use tui_realm_stdlib::Table;
-use tuirealm::command::{Cmd, Direction, Position};
-use tuirealm::event::{Key, KeyEvent};
-use tuirealm::props::{Alignment, BorderType, Borders, Color, TableBuilder, TextSpan};
-use tuirealm::{AttrValue, Attribute, Component, Event, MockComponent};
+use tuirealm::command::{Cmd, CmdResult, Direction, Position};
+use tuirealm::event::{Key, KeyEvent, KeyModifiers};
+use tuirealm::props::{
+    Alignment, BorderType, Borders, Color, PropPayload, PropValue, TableBuilder, TextSpan,
+};
+use tuirealm::tui::layout::Rect;
+use tuirealm::{AttrValue, Attribute, Component, Event, Frame, MockComponent};
+use uuid::Uuid;
 
+use crate::app::notifications::{Notification, Severity};
+use crate::app::tasks::{ActionInfo, TaskInfo, TaskStatus};
 use crate::constants::{Msg, ServerHandle, ServerListStatus, State, UserEvent, UserEventIter};
+use crate::providers::{ActionStatus, PowerAction, Server};
 
-#[derive(MockComponent)]
+/// Server table, actions apply to the marked servers or else the selected one.
+/// Setting `Custom("mark")` to a filter marks every server matching it.
 pub struct ServerListConnected {
     component: Table,
     servers: Option<Vec<ServerHandle>>,
+    /// Account and id of the marked servers
+    marked: BTreeSet<(String, String)>,
 }
 
 impl Default for ServerListConnected {
@@ -21,36 +33,56 @@ impl Default for ServerListConnected {
                         .modifiers(BorderType::Rounded)
                         .color(Color::Yellow),
                 )
-                .title(" Servers List ", Alignment::Center)
+                .title(
+                    " Servers List (Space/*/u: mark/mark matching/unmark, d: delete, \
+                     o/s/f: on/shutdown/off, r/R: reboot/reset, l: label) ",
+                    Alignment::Center,
+                )
                 .scroll(true)
                 .highlighted_color(Color::LightYellow)
                 .highlighted_str(">")
                 .rewind(true)
                 .step(4)
                 .row_height(1)
-                .headers(&["No", "Name", "Status", "IP"])
+                .headers(&["No", "Name", "Account", "Status", "IP"])
                 .table(
                     TableBuilder::default()
                         .add_col(TextSpan::new(""))
                         .add_col(TextSpan::new("No servers detected"))
                         .add_col(TextSpan::new(""))
                         .add_col(TextSpan::new(""))
+                        .add_col(TextSpan::new(""))
                         .add_row()
                         .add_col(TextSpan::new("+"))
                         .add_col(TextSpan::new("Create a new server"))
                         .add_col(TextSpan::new(""))
                         .add_col(TextSpan::new(""))
+                        .add_col(TextSpan::new(""))
                         .add_row()
                         .build(),
                 ),
             servers: None,
+            marked: BTreeSet::new(),
         }
     }
 }
 
 impl ServerListConnected {
     fn update_status(&mut self, status: ServerListStatus) {
-        self.servers = Some(status.servers);
+        let selected = self.selected();
+        let previous = self.servers.replace(status.servers).unwrap_or_default();
+        // Servers gone since the last refresh cannot stay marked
+        let listed = self
+            .servers
+            .iter()
+            .flatten()
+            .filter_map(|server| match server {
+                ServerHandle::Server(server) => Some(key(server)),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        self.marked.retain(|marked| listed.contains(marked));
+
         let mut table = TableBuilder::default();
         self.servers
             .as_ref()
@@ -59,9 +91,18 @@ impl ServerListConnected {
             .enumerate()
             .for_each(|(index, server)| {
                 let status = server.to_status().unwrap();
+                let marked = match server {
+                    ServerHandle::Server(server) => self.marked.contains(&key(server)),
+                    _ => false,
+                };
                 table
-                    .add_col(TextSpan::new(format!("{}", index + 1)))
+                    .add_col(TextSpan::new(format!(
+                        "{}{}",
+                        if marked { "*" } else { " " },
+                        index + 1
+                    )))
                     .add_col(TextSpan::new(&status.name))
+                    .add_col(TextSpan::new(&status.account))
                     .add_col(TextSpan::new(&status.status))
                     .add_col(TextSpan::new(&status.ip))
                     .add_row();
@@ -71,9 +112,177 @@ impl ServerListConnected {
             .add_col(TextSpan::new("Create a new server"))
             .add_col(TextSpan::new(""))
             .add_col(TextSpan::new(""))
+            .add_col(TextSpan::new(""))
             .add_row();
         self.component
-            .attr(Attribute::Content, AttrValue::Table(table.build()))
+            .attr(Attribute::Content, AttrValue::Table(table.build()));
+
+        // Follow the selected server, or stay on a server row when it is gone
+        let servers = self.servers.as_deref().unwrap_or_default();
+        let selected = match previous.get(selected) {
+            Some(ServerHandle::Server(server)) => servers
+                .iter()
+                .position(|other| match other {
+                    ServerHandle::Server(other) => key(other) == key(server),
+                    _ => false,
+                })
+                .unwrap_or(selected.min(servers.len().saturating_sub(1))),
+            _ => servers.len().min(selected),
+        };
+        self.component.attr(
+            Attribute::Value,
+            AttrValue::Payload(PropPayload::One(PropValue::Usize(selected))),
+        );
+
+        // Lets the create form reject names that are already taken
+        let names = self
+            .servers
+            .iter()
+            .flatten()
+            .filter_map(|server| match server {
+                ServerHandle::Server(server) => Some(PropValue::Str(server.name.clone())),
+                _ => None,
+            })
+            .collect();
+        self.component.attr(
+            Attribute::Custom("names"),
+            AttrValue::Payload(PropPayload::Vec(names)),
+        );
+    }
+}
+
+impl ServerListConnected {
+    fn selected(&self) -> usize {
+        self.component.state().unwrap_one().unwrap_usize()
+    }
+
+    fn selected_server(&self) -> Option<ServerHandle> {
+        self.servers.as_ref()?.get(self.selected()).cloned()
+    }
+
+    /// The marked servers, or the selected one when none are marked.
+    fn targets(&self) -> Vec<Server> {
+        let selected = self.selected();
+        self.servers
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(index, server)| match server {
+                ServerHandle::Server(server) => Some((index, server)),
+                _ => None,
+            })
+            .filter(|(index, server)| match self.marked.is_empty() {
+                true => *index == selected,
+                false => self.marked.contains(&key(server)),
+            })
+            .map(|(_, server)| *server.clone())
+            .collect()
+    }
+
+    fn refresh(&mut self) {
+        let servers = self.servers.take().unwrap_or_default();
+        self.update_status(ServerListStatus::new(servers));
+    }
+
+    fn toggle(&mut self) -> Option<Msg> {
+        let selected = self.selected();
+        let Some(ServerHandle::Server(server)) = self.servers.as_ref()?.get(selected) else {
+            return None;
+        };
+        let key = key(server);
+        if !self.marked.remove(&key) {
+            self.marked.insert(key);
+        }
+        self.refresh();
+        Some(self.marked_info())
+    }
+
+    /// Marks the servers matching a filter, an empty filter marks them all.
+    fn mark(&mut self, filter: &str) {
+        let matching = self
+            .servers
+            .iter()
+            .flatten()
+            .filter_map(|server| match server {
+                ServerHandle::Server(server) if matches(server, filter) => Some(key(server)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.marked.extend(matching);
+        self.refresh();
+    }
+
+    fn marked_info(&self) -> Msg {
+        Msg::Notify(
+            Severity::Info,
+            format!("{} servers marked", self.marked.len()),
+        )
+    }
+
+    /// Shows the target servers as transitioning until the next refresh.
+    fn power(&mut self, action: PowerAction) -> Option<Msg> {
+        let targets = self.targets();
+        if targets.is_empty() {
+            return None;
+        }
+
+        for server in self.servers.iter_mut().flatten() {
+            if let ServerHandle::Server(server) = server {
+                if targets.iter().any(|target| key(target) == key(server)) {
+                    server.state = action.pending_state();
+                }
+            }
+        }
+        self.refresh();
+        Some(Msg::Power(targets, action))
+    }
+}
+
+fn key(server: &Server) -> (String, String) {
+    (server.account.clone(), server.id.clone())
+}
+
+/// Every word of the filter must match the name, a label written as
+/// `key=value`, or the exact status or account.
+fn matches(server: &Server, filter: &str) -> bool {
+    filter.split_whitespace().all(|word| {
+        let word = word.to_lowercase();
+        match word.split_once('=') {
+            Some((key, value)) => server
+                .labels
+                .iter()
+                .any(|(k, v)| k.to_lowercase() == key && v.to_lowercase() == value),
+            None => {
+                server.name.to_lowercase().contains(&word)
+                    || server.state.to_string().to_lowercase() == word
+                    || server.account.to_lowercase() == word
+            }
+        }
+    })
+}
+
+impl MockComponent for ServerListConnected {
+    fn view(&mut self, frame: &mut Frame, area: Rect) {
+        self.component.view(frame, area);
+    }
+
+    fn query(&self, attr: Attribute) -> Option<AttrValue> {
+        self.component.query(attr)
+    }
+
+    fn attr(&mut self, attr: Attribute, value: AttrValue) {
+        match (attr, value) {
+            (Attribute::Custom("mark"), AttrValue::String(filter)) => self.mark(&filter),
+            (attr, value) => self.component.attr(attr, value),
+        }
+    }
+
+    fn state(&self) -> tuirealm::State {
+        self.component.state()
+    }
+
+    fn perform(&mut self, cmd: Cmd) -> CmdResult {
+        self.component.perform(cmd)
     }
 }
 
@@ -95,13 +304,93 @@ impl Component<Msg, UserEventIter> for ServerListConnected {
                 code: Key::Home, ..
             }) => Cmd::GoTo(Position::Begin),
             Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::Keyboard(KeyEvent {
+                code: Key::Delete | Key::Char('d'),
+                ..
+            }) => {
+                let targets = self.targets();
+                return (!targets.is_empty()).then_some(Msg::RequestDelete(targets));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('l'),
+                ..
+            }) => {
+                let targets = self.targets();
+                return (!targets.is_empty()).then_some(Msg::RequestLabel(targets));
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(' '),
+                ..
+            }) => return self.toggle(),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('*'),
+                ..
+            }) => return Some(Msg::RequestMark),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('u'),
+                ..
+            }) => {
+                self.marked.clear();
+                self.refresh();
+                return Some(self.marked_info());
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('x'),
+                ..
+            }) => return Some(Msg::CloseBatch),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char(key @ ('o' | 's' | 'f' | 'r' | 'R')),
+                ..
+            }) => {
+                let action = match key {
+                    'o' => PowerAction::PowerOn,
+                    's' => PowerAction::Shutdown,
+                    'f' => PowerAction::PowerOff,
+                    'r' => PowerAction::Reboot,
+                    _ => PowerAction::Reset,
+                };
+                return self.power(action);
+            }
             Event::User(UserEventIter { events }) => {
+                let mut cmd = Cmd::None;
+                let mut polled = false;
                 for ev in events {
-                    if let UserEvent::ServerListStatus(status) = ev {
-                        self.update_status(status);
+                    match ev {
+                        UserEvent::ServerListStatus(status) => {
+                            self.update_status(status);
+                            cmd = Cmd::Change;
+                        }
+                        // Background refreshes and partial listings only update the
+                        // preview when the selected server changed, and never open
+                        // the create form
+                        UserEvent::Polled(_, Some(status)) => {
+                            let before = self.selected_server();
+                            self.update_status(status);
+                            let after = self.selected_server();
+                            if after.is_some() && after != before {
+                                cmd = Cmd::Change;
+                            }
+                            polled = true;
+                        }
+                        UserEvent::ServerPage(status) => {
+                            // Marks on pages still to come are not dropped
+                            let marked = self.marked.clone();
+                            let before = self.selected_server();
+                            self.update_status(status);
+                            self.marked = marked;
+                            let after = self.selected_server();
+                            if after.is_some() && after != before {
+                                cmd = Cmd::Change;
+                            }
+                            polled = true;
+                        }
+                        _ => {}
                     }
                 }
-                Cmd::Change
+                if polled && cmd == Cmd::None {
+                    return Some(Msg::Nop(0));
+                }
+                cmd
             }
             _ => Cmd::None,
         };
@@ -125,3 +414,354 @@ impl Component<Msg, UserEventIter> for ServerListConnected {
         }
     }
 }
+
+/// Running and recently finished tasks, newest first.
+#[derive(MockComponent)]
+pub struct TaskPanel {
+    component: Table,
+    tasks: Vec<TaskInfo>,
+}
+
+impl TaskPanel {
+    pub fn new(mut tasks: Vec<TaskInfo>) -> Self {
+        tasks.reverse();
+        let mut panel = Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::LightBlue),
+                )
+                .title(" Tasks (c: cancel, Esc/Ctrl+t: close) ", Alignment::Center)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str(">")
+                .step(4)
+                .row_height(1)
+                .headers(&["Status", "Time", "Task"])
+                .widths(&[15, 10, 75]),
+            tasks,
+        };
+        panel.render(None);
+        panel
+    }
+
+    fn selected(&self) -> usize {
+        self.component.state().unwrap_one().unwrap_usize()
+    }
+
+    fn selected_task(&self) -> Option<&TaskInfo> {
+        self.tasks.get(self.selected())
+    }
+
+    fn update(&mut self, info: TaskInfo) {
+        match self.tasks.iter_mut().find(|task| task.id == info.id) {
+            Some(task) => *task = info,
+            None => self.tasks.insert(0, info),
+        }
+    }
+
+    /// Keeps the selected task selected while new ones come in on top.
+    fn render(&mut self, selected: Option<Uuid>) {
+        let mut table = TableBuilder::default();
+        for task in &self.tasks {
+            let (status, color) = match &task.status {
+                TaskStatus::Running => match task.progress {
+                    Some((done, total)) => (format!("running {}/{}", done, total), Color::Yellow),
+                    None => ("running".to_string(), Color::Yellow),
+                },
+                TaskStatus::Done => ("done".to_string(), Color::Green),
+                TaskStatus::Failed(_) => ("failed".to_string(), Color::Red),
+                other => (other.to_string(), Color::Red),
+            };
+            let name = match &task.status {
+                TaskStatus::Failed(err) => format!("{}: {}", task.name, err),
+                _ => task.name.clone(),
+            };
+            table
+                .add_col(TextSpan::new(status).fg(color))
+                .add_col(TextSpan::new(format!(
+                    "{:.1}s",
+                    task.elapsed().as_secs_f32()
+                )))
+                .add_col(TextSpan::new(name))
+                .add_row();
+        }
+        if self.tasks.is_empty() {
+            table
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new("No tasks yet"))
+                .add_row();
+        }
+        self.component
+            .attr(Attribute::Content, AttrValue::Table(table.build()));
+
+        let selected = selected
+            .and_then(|id| self.tasks.iter().position(|task| task.id == id))
+            .unwrap_or_default();
+        self.component.attr(
+            Attribute::Value,
+            AttrValue::Payload(PropPayload::One(PropValue::Usize(selected))),
+        );
+    }
+}
+
+impl Component<Msg, UserEventIter> for TaskPanel {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => return Some(Msg::CloseTasks),
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::Keyboard(KeyEvent {
+                code: Key::Char('c'),
+                modifiers: KeyModifiers::NONE,
+            }) => {
+                return self
+                    .selected_task()
+                    .filter(|task| task.status == TaskStatus::Running)
+                    .map(|task| Msg::CancelTask(task.id));
+            }
+            Event::User(UserEventIter { events }) => {
+                let selected = self.selected_task().map(|task| task.id);
+                for ev in events {
+                    if let UserEvent::TaskProgress(info) = ev {
+                        self.update(info);
+                    }
+                }
+                self.render(selected);
+                return Some(Msg::Nop(0));
+            }
+            // Keeps the time of running tasks going
+            Event::Tick => {
+                if !self
+                    .tasks
+                    .iter()
+                    .any(|task| task.status == TaskStatus::Running)
+                {
+                    return None;
+                }
+                self.render(self.selected_task().map(|task| task.id));
+                return Some(Msg::Nop(0));
+            }
+            _ => Cmd::None,
+        };
+
+        match self.perform(cmd) {
+            CmdResult::None => None,
+            _ => Some(Msg::Nop(0)),
+        }
+    }
+}
+
+/// Everything carton reported this session, newest first.
+#[derive(MockComponent)]
+pub struct NotificationPanel {
+    component: Table,
+}
+
+impl NotificationPanel {
+    pub fn new(notifications: Vec<Notification>) -> Self {
+        let mut table = TableBuilder::default();
+        for notification in notifications.iter().rev() {
+            table
+                .add_col(TextSpan::new(notification.clock()))
+                .add_col(
+                    TextSpan::new(notification.severity.to_string())
+                        .fg(notification.severity.color()),
+                )
+                .add_col(TextSpan::new(&notification.message))
+                .add_row();
+        }
+        if notifications.is_empty() {
+            table
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new("Nothing reported yet"))
+                .add_row();
+        }
+
+        Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Double)
+                        .color(Color::LightBlue),
+                )
+                .title(" Notifications (Esc/Ctrl+n: close) ", Alignment::Center)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .highlighted_str(">")
+                .step(4)
+                .row_height(1)
+                .headers(&["Time", "Severity", "Message"])
+                .widths(&[10, 10, 80])
+                .table(table.build()),
+        }
+    }
+}
+
+impl Component<Msg, UserEventIter> for NotificationPanel {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent { code: Key::Esc, .. }) => {
+                return Some(Msg::CloseNotifications)
+            }
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            _ => Cmd::None,
+        };
+
+        match self.perform(cmd) {
+            CmdResult::None => None,
+            _ => Some(Msg::Nop(0)),
+        }
+    }
+}
+
+/// Provider actions started by carton with their progress, newest first.
+#[derive(MockComponent)]
+pub struct ActionLog {
+    component: Table,
+    actions: Vec<ActionInfo>,
+}
+
+impl ActionLog {
+    pub fn new(mut actions: Vec<ActionInfo>) -> Self {
+        actions.reverse();
+        let mut log = Self {
+            component: Table::default()
+                .borders(
+                    Borders::default()
+                        .modifiers(BorderType::Rounded)
+                        .color(Color::LightBlue),
+                )
+                .title(" Action log ", Alignment::Left)
+                .scroll(true)
+                .highlighted_color(Color::LightBlue)
+                .step(4)
+                .row_height(1)
+                .headers(&["Action", "On", "Account", "Progress"])
+                .widths(&[25, 25, 20, 30]),
+            actions,
+        };
+        log.render();
+        log
+    }
+
+    fn log(&mut self, info: ActionInfo) {
+        match self
+            .actions
+            .iter_mut()
+            .find(|logged| logged.account == info.account && logged.action.id == info.action.id)
+        {
+            Some(logged) => *logged = info,
+            None => self.actions.insert(0, info),
+        }
+    }
+
+    fn render(&mut self) {
+        let mut table = TableBuilder::default();
+        for info in &self.actions {
+            let progress = match &info.action.status {
+                ActionStatus::Running => {
+                    TextSpan::new(format!("{}%", info.action.progress)).fg(Color::Yellow)
+                }
+                ActionStatus::Success => TextSpan::new("done").fg(Color::Green),
+                ActionStatus::Error(err) => {
+                    TextSpan::new(format!("failed: {}", err)).fg(Color::Red)
+                }
+            };
+            table
+                .add_col(TextSpan::new(&info.action.command))
+                .add_col(TextSpan::new(&info.subject))
+                .add_col(TextSpan::new(&info.account))
+                .add_col(progress)
+                .add_row();
+        }
+        if self.actions.is_empty() {
+            table
+                .add_col(TextSpan::new("No actions yet"))
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new(""))
+                .add_col(TextSpan::new(""))
+                .add_row();
+        }
+        self.component
+            .attr(Attribute::Content, AttrValue::Table(table.build()));
+    }
+}
+
+impl Component<Msg, UserEventIter> for ActionLog {
+    fn on(&mut self, ev: Event<UserEventIter>) -> Option<Msg> {
+        let cmd = match ev {
+            Event::Keyboard(KeyEvent {
+                code: Key::Down, ..
+            }) => Cmd::Move(Direction::Down),
+            Event::Keyboard(KeyEvent { code: Key::Up, .. }) => Cmd::Move(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageDown,
+                ..
+            }) => Cmd::Scroll(Direction::Down),
+            Event::Keyboard(KeyEvent {
+                code: Key::PageUp, ..
+            }) => Cmd::Scroll(Direction::Up),
+            Event::Keyboard(KeyEvent {
+                code: Key::Home, ..
+            }) => Cmd::GoTo(Position::Begin),
+            Event::Keyboard(KeyEvent { code: Key::End, .. }) => Cmd::GoTo(Position::End),
+            Event::User(UserEventIter { events }) => {
+                let before = self.actions.len();
+                for ev in events {
+                    if let UserEvent::ActionProgress(info) = ev {
+                        self.log(info);
+                    }
+                }
+                self.render();
+                // Stay on the same action while new ones come in on top
+                if before > 0 && self.actions.len() > before {
+                    let selected = self.component.state().unwrap_one().unwrap_usize();
+                    self.component.attr(
+                        Attribute::Value,
+                        AttrValue::Payload(PropPayload::One(PropValue::Usize(
+                            selected + self.actions.len() - before,
+                        ))),
+                    );
+                }
+                return Some(Msg::Nop(0));
+            }
+            _ => Cmd::None,
+        };
+
+        match self.perform(cmd) {
+            CmdResult::None => None,
+            _ => Some(Msg::Nop(0)),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::constants::{AuthPlatform, Defaults};
+use crate::credentials;
+use crate::providers::CreateServer;
+
+/// On-disk configuration, read from `$XDG_CONFIG_HOME/carton/config.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_profile: Option<String>,
+    /// Directory of cloud-init templates, `$XDG_CONFIG_HOME/carton/templates` by default
+    pub templates: Option<PathBuf>,
+    /// Seconds between background refreshes of the terminal UI, 0 turns them off
+    pub refresh_interval: Option<u64>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    pub blueprints: BTreeMap<String, Blueprint>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub provider: AuthPlatform,
+    pub token: Option<String>,
+    pub token_command: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub image: Option<String>,
+    pub location: Option<String>,
+    pub server_type: Option<String>,
+    #[serde(default)]
+    pub ssh_keys: Vec<String>,
+    /// Template name or file with the cloud-init user data for new servers
+    pub user_data: Option<String>,
+}
+
+impl Profile {
+    pub fn defaults(&self) -> Defaults {
+        Defaults {
+            image: self.image.clone(),
+            location: self.location.clone(),
+            server_type: self.server_type.clone(),
+            ssh_keys: self.ssh_keys.clone(),
+            user_data: self.user_data.clone(),
+            templates: None,
+        }
+    }
+
+    /// Resolves the profile token, preferring `token_command` over `token`, then
+    /// a token stored with `carton auth add` and finally the provider environment.
+    pub fn token(&self, name: &str) -> Result<String> {
+        match (&self.token, &self.token_command) {
+            (Some(_), Some(_)) => {
+                bail!("Profile {} sets both token and token_command", name)
+            }
+            (_, Some(command)) => run_token_command(command)
+                .with_context(|| format!("token_command of profile {} failed", name)),
+            (Some(token), None) => Ok(token.clone()),
+            (None, None) => match credentials::load(name)? {
+                Some(token) => Ok(token),
+                None => env_token(&self.provider).with_context(|| {
+                    format!(
+                        "Profile {} has no token, token_command, stored token or environment",
+                        name
+                    )
+                }),
+            },
+        }
+    }
+}
+
+/// Named preset for new servers, pre-filling whatever the creation leaves out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Blueprint {
+    pub server_type: Option<String>,
+    pub image: Option<String>,
+    pub location: Option<String>,
+    pub datacenter: Option<String>,
+    #[serde(default)]
+    pub ssh_keys: Vec<String>,
+    pub ipv4: Option<bool>,
+    pub ipv6: Option<bool>,
+    #[serde(default)]
+    pub networks: Vec<String>,
+    #[serde(default)]
+    pub firewalls: Vec<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Template name or file with the cloud-init user data
+    pub user_data: Option<String>,
+}
+
+impl Blueprint {
+    /// Fills the fields a create request leaves empty, labels given on the
+    /// request win over the blueprint's.
+    pub fn apply(&self, request: &mut CreateServer) {
+        let fill = |value: &mut String, preset: &Option<String>| {
+            if let Some(preset) = preset.as_ref().filter(|_| value.trim().is_empty()) {
+                *value = preset.clone();
+            }
+        };
+        fill(&mut request.server_type, &self.server_type);
+        fill(&mut request.image, &self.image);
+        if request.location.is_none() && request.datacenter.is_none() {
+            request.location = self.location.clone();
+            request.datacenter = self.datacenter.clone();
+        }
+        for (list, preset) in [
+            (&mut request.ssh_keys, &self.ssh_keys),
+            (&mut request.networks, &self.networks),
+            (&mut request.firewalls, &self.firewalls),
+        ] {
+            if list.is_empty() {
+                list.clone_from(preset);
+            }
+        }
+        for (key, value) in &self.labels {
+            request
+                .labels
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    /// First free `<blueprint>-<n>` name among the existing servers.
+    pub fn next_name(blueprint: &str, servers: &[String]) -> String {
+        (1..)
+            .map(|n| format!("{}-{}", blueprint, n))
+            .find(|name| !servers.contains(name))
+            .unwrap_or_default()
+    }
+}
+
+/// Resolves carton's directory under an XDG base directory, e.g.
+/// `xdg_dir("XDG_CONFIG_HOME", ".config")` for `~/.config/carton`.
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))
+        .map(|dir| dir.join("carton"))
+}
+
+impl ConfigFile {
+    /// Default location of the config file, following the XDG base directory spec.
+    pub fn default_path() -> Option<PathBuf> {
+        xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("config.toml"))
+    }
+
+    /// Loads the given file, or the default one if it exists.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let file: Self = toml::from_str(&content)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        if let Some(profile) = file.default_profile.as_ref() {
+            if !file.profiles.contains_key(profile) {
+                bail!("Default profile {} is not defined", profile);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Every 30 seconds unless configured otherwise.
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        match self.refresh_interval.unwrap_or(30) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    pub fn templates_dir(&self) -> Option<PathBuf> {
+        self.templates
+            .clone()
+            .or_else(|| xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("templates")))
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles
+            .get(name)
+            .with_context(|| format!("Profile {} is not defined", name))
+    }
+}
+
+fn run_token_command(command: &str) -> Result<String> {
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    if !output.status.success() {
+        bail!("{} exited with {}", command, output.status);
+    }
+
+    let token = String::from_utf8(output.stdout)?.trim().to_string();
+    if token.is_empty() {
+        bail!("{} printed no token", command);
+    }
+    Ok(token)
+}
+
+/// Reads the credentials the provider's own tooling would pick up.
+pub fn env_token(provider: &AuthPlatform) -> Result<String> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .with_context(|| format!("{} is not set", name))
+    };
+
+    match provider {
+        AuthPlatform::Hetzner => var("HCLOUD_TOKEN"),
+        AuthPlatform::Google => var("GOOGLE_APPLICATION_CREDENTIALS"),
+        AuthPlatform::Amazon => {
+            let mut token = format!(
+                "{}:{}",
+                var("AWS_ACCESS_KEY_ID")?,
+                var("AWS_SECRET_ACCESS_KEY")?
+            );
+            if let Ok(session) = var("AWS_SESSION_TOKEN") {
+                token = format!("{}:{}", token, session);
+            }
+            Ok(token)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn blueprint() -> Blueprint {
+        Blueprint {
+            server_type: Some("cx22".to_string()),
+            image: Some("fedora-41".to_string()),
+            location: Some("fsn1".to_string()),
+            datacenter: Some("fsn1-dc14".to_string()),
+            ssh_keys: strings(&["admin"]),
+            networks: strings(&["internal"]),
+            firewalls: strings(&["web"]),
+            labels: BTreeMap::from([
+                ("role".to_string(), "web".to_string()),
+                ("env".to_string(), "prod".to_string()),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn apply_fills_empty_fields() {
+        let mut request = CreateServer {
+            name: "web-1".to_string(),
+            server_type: " ".to_string(),
+            ..Default::default()
+        };
+        blueprint().apply(&mut request);
+        assert_eq!(request.name, "web-1");
+        assert_eq!(request.server_type, "cx22");
+        assert_eq!(request.image, "fedora-41");
+        assert_eq!(request.location.as_deref(), Some("fsn1"));
+        assert_eq!(request.datacenter.as_deref(), Some("fsn1-dc14"));
+        assert_eq!(request.ssh_keys, strings(&["admin"]));
+        assert_eq!(request.networks, strings(&["internal"]));
+        assert_eq!(request.firewalls, strings(&["web"]));
+        assert_eq!(request.labels, blueprint().labels);
+    }
+
+    #[test]
+    fn apply_keeps_given_fields() {
+        let mut request = CreateServer {
+            server_type: "cx32".to_string(),
+            image: "ubuntu-24.04".to_string(),
+            datacenter: Some("nbg1-dc3".to_string()),
+            ssh_keys: strings(&["me"]),
+            labels: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+            ..Default::default()
+        };
+        blueprint().apply(&mut request);
+        assert_eq!(request.server_type, "cx32");
+        assert_eq!(request.image, "ubuntu-24.04");
+        // A given placement is not mixed with the blueprint's
+        assert_eq!(request.location, None);
+        assert_eq!(request.datacenter.as_deref(), Some("nbg1-dc3"));
+        assert_eq!(request.ssh_keys, strings(&["me"]));
+        assert_eq!(request.networks, strings(&["internal"]));
+        assert_eq!(request.labels["env"], "dev");
+        assert_eq!(request.labels["role"], "web");
+    }
+
+    #[test]
+    fn next_name_takes_first_free_number() {
+        assert_eq!(Blueprint::next_name("web", &[]), "web-1");
+        assert_eq!(
+            Blueprint::next_name("web", &strings(&["web-1", "web-2", "db-3"])),
+            "web-3"
+        );
+        assert_eq!(
+            Blueprint::next_name("web", &strings(&["web-2", "web-3"])),
+            "web-1"
+        );
+    }
+}
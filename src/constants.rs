@@ -1,24 +1,194 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use clap::Parser;
-use hcloud::models::{server::Status, Server};
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use tuirealm::Component;
+use uuid::Uuid;
 
+use crate::app::notifications::{Notification, Severity};
+use crate::app::tasks::{ActionInfo, TaskInfo};
+use crate::cloud_init::{self, Template};
 use crate::components::{
     container::{Header, Preview},
     input::TextInput,
     label::TextLabel,
-    paragraph::ServerListDisconnected,
-    table::ServerListConnected,
+    modal::{DeleteModal, PromptModal},
+    paragraph::{
+        BatchProgress, DebugLog, ErrorPane, ServerListDisconnected, Toasts, UserDataPreview,
+    },
+    picker::Picker,
+    table::{ActionLog, NotificationPanel, ServerListConnected, TaskPanel},
+};
+use crate::config::{self, Blueprint, ConfigFile};
+use crate::error::ErrorReport;
+use crate::logging::LogLevel;
+use crate::providers::http::Quota;
+use crate::providers::{
+    CreateServer, Image, Location, PowerAction, Resource, Server, ServerState, ServerType,
 };
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
+    /// Config file to read instead of `$XDG_CONFIG_HOME/carton/config.toml`
+    #[arg(short, long)]
+    pub(crate) config: Option<PathBuf>,
+    /// Profile from the config file to load, can be given multiple times
+    #[arg(short, long = "profile", value_name = "NAME")]
+    pub(crate) profiles: Vec<String>,
+    /// Provider of an ad-hoc account, the token falls back to the provider environment
     #[arg(short, long)]
-    pub(crate) auth: AuthPlatform,
+    pub(crate) auth: Option<AuthPlatform>,
+    /// API token, `ACCESS_KEY_ID:SECRET_ACCESS_KEY` for Amazon or a service-account key file for Google
+    #[arg(short, long, requires = "auth")]
+    pub(crate) token: Option<String>,
+    /// Additional named account, can be given multiple times
+    #[arg(long = "account", value_name = "NAME:PROVIDER:TOKEN")]
+    pub(crate) accounts: Vec<String>,
+    /// Region to operate in, for providers that are region-scoped
     #[arg(short, long)]
-    pub(crate) token: String,
+    pub(crate) region: Option<String>,
+    /// Override the provider API endpoint, e.g. to target a local mock
+    #[arg(short, long)]
+    pub(crate) endpoint: Option<String>,
+    /// Output format of the subcommands
+    #[arg(short, long, global = true, value_enum, default_value_t = Output::Table)]
+    pub(crate) output: Output,
+    /// Lowest level written to the log file in `$XDG_STATE_HOME/carton`
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Info)]
+    pub(crate) log_level: LogLevel,
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    Table,
+    Json,
+    Yaml,
+    Csv,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Manage stored provider tokens
+    #[command(subcommand)]
+    Auth(AuthCommand),
+    /// List, create and delete servers without the terminal UI
+    #[command(subcommand)]
+    Servers(ServersCommand),
+    /// Create a server, same as `servers create`
+    Create(Box<CreateCommand>),
+    /// Reconcile the servers of an account with a YAML fleet file
+    #[command(subcommand)]
+    Fleet(FleetCommand),
+    /// Print the connection status and resource counts of every account
+    Status,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ServersCommand {
+    /// List the servers of every account
+    List,
+    /// Show the details of a server by name or id
+    Show { server: String },
+    /// Create a server on the first account, select it with --profile
+    Create(Box<CreateCommand>),
+    /// Delete a server by name or id
+    Delete {
+        server: String,
+        /// Skip the confirmation prompt, required when stdin is not a terminal
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FleetCommand {
+    /// Print the changes that would bring the account in line with the file
+    Plan { file: PathBuf },
+    /// Make the planned changes after confirmation
+    Apply {
+        file: PathBuf,
+        /// Skip the confirmation prompt, required when stdin is not a terminal
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CreateCommand {
+    /// Server name, defaults to the next free <blueprint>-<n> with --blueprint
+    pub name: Option<String>,
+    /// Blueprint from the config file to start from, other flags override it
+    #[arg(short, long)]
+    pub blueprint: Option<String>,
+    /// Server type, defaults to the profile's server_type
+    #[arg(long = "type", default_value = "")]
+    pub server_type: String,
+    /// Image, defaults to the profile's image
+    #[arg(long, default_value = "")]
+    pub image: String,
+    /// Location, defaults to the profile's location
+    #[arg(long)]
+    pub location: Option<String>,
+    /// Datacenter to pin the server to, replaces the location
+    #[arg(long)]
+    pub datacenter: Option<String>,
+    /// SSH key to install, can be given multiple times, defaults to the profile's ssh_keys
+    #[arg(long = "ssh-key", value_name = "NAME")]
+    pub ssh_keys: Vec<String>,
+    /// Give the server a public IPv4 address
+    #[arg(long)]
+    pub ipv4: bool,
+    /// Create the server without a public IPv6 address
+    #[arg(long)]
+    pub no_ipv6: bool,
+    /// Existing primary IP to attach, can be given multiple times
+    #[arg(long = "primary-ip", value_name = "ID")]
+    pub primary_ips: Vec<String>,
+    /// Private network to attach, can be given multiple times
+    #[arg(long = "network", value_name = "ID")]
+    pub networks: Vec<String>,
+    /// Firewall to apply, can be given multiple times
+    #[arg(long = "firewall", value_name = "ID")]
+    pub firewalls: Vec<String>,
+    /// Cloud-init template name or file, defaults to the profile's user_data
+    #[arg(long, value_name = "TEMPLATE|FILE")]
+    pub user_data: Option<String>,
+    /// Label as KEY=VALUE, can be given multiple times
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub labels: Vec<(String, String)>,
+}
+
+pub fn parse_label(label: &str) -> Result<(String, String)> {
+    let (key, value) = label
+        .split_once('=')
+        .ok_or(anyhow!("Invalid label {}, expected KEY=VALUE", label))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AuthCommand {
+    /// Store a token for a profile, prompting for it without echo
+    Add {
+        /// Profile the token belongs to
+        name: String,
+        /// Use the passphrase-encrypted file even if a keyring is available
+        #[arg(long)]
+        file: bool,
+    },
+    /// List the profiles with a stored token
+    List,
+    /// Remove a stored token
+    Remove {
+        /// Profile the token belongs to
+        name: String,
+    },
 }
 
 #[derive(Debug, PartialEq)]
@@ -32,10 +202,36 @@ pub enum Msg {
     UpdateState(State),
     Input(InputId, String),
     UpdateProviderStatus,
+    SelectAccount(Option<String>),
     FetchServers,
     Submit,
-    #[allow(dead_code)]
-    Info(String),
+    /// Servers to delete, the marked ones or the selected one
+    RequestDelete(Vec<Server>),
+    Power(Vec<Server>, PowerAction),
+    ConfirmDelete(String),
+    /// Asks for the filter of the servers to mark
+    RequestMark,
+    Mark(String),
+    RequestLabel(Vec<Server>),
+    ConfirmLabel(String),
+    CloseBatch,
+    CloseModal,
+    ToggleTasks,
+    CloseTasks,
+    CancelTask(Uuid),
+    /// Errors and notices the tasks sent, the last error is kept for the
+    /// error pane
+    Reported(Vec<UserEvent>),
+    ToggleError,
+    CloseError,
+    ToggleNotifications,
+    CloseNotifications,
+    DismissToasts,
+    /// Toasts may have run out their time
+    ExpireToasts,
+    ToggleDebug,
+    CloseDebug,
+    Notify(Severity, String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -43,6 +239,33 @@ pub enum InterfaceMsg {
     Connected,
     Disconnected,
     SelectedServer(ServerHandle),
+    /// Text to type back and what it deletes
+    OpenDeleteModal(String, String),
+    OpenPrompt(Prompt),
+    /// Title and server names of a batch that just started
+    ShowBatch(String, Vec<String>),
+    CloseBatch,
+    CloseModal,
+    OpenTasks(Vec<TaskInfo>),
+    /// Actions to list in a freshly mounted action log
+    ShowActions(Vec<ActionInfo>),
+    CloseTasks,
+    OpenError(Box<ErrorReport>),
+    CloseError,
+    OpenNotifications(Vec<Notification>),
+    CloseNotifications,
+    /// Toasts to show, none takes the toast area down
+    ShowToasts(Vec<Notification>),
+    OpenDebug,
+    CloseDebug,
+    PreviewUserData(String),
+}
+
+/// Single line questions asked in a modal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prompt {
+    Mark,
+    Label,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -51,6 +274,13 @@ pub enum InputId {
     CreateServerDatacenter,
     CreateServerImage,
     CreateServerType,
+    CreateServerLocation,
+    CreateServerSshKeys,
+    CreateServerPublicNet,
+    CreateServerNetworks,
+    CreateServerFirewalls,
+    CreateServerUserData,
+    CreateServerBlueprint,
     #[allow(dead_code)]
     Empty,
 }
@@ -62,6 +292,13 @@ impl std::fmt::Display for InputId {
             InputId::CreateServerDatacenter => write!(f, "CreateServerDatacenter"),
             InputId::CreateServerImage => write!(f, "CreateServerImage"),
             InputId::CreateServerType => write!(f, "CreateServerType"),
+            InputId::CreateServerLocation => write!(f, "CreateServerLocation"),
+            InputId::CreateServerSshKeys => write!(f, "CreateServerSshKeys"),
+            InputId::CreateServerPublicNet => write!(f, "CreateServerPublicNet"),
+            InputId::CreateServerNetworks => write!(f, "CreateServerNetworks"),
+            InputId::CreateServerFirewalls => write!(f, "CreateServerFirewalls"),
+            InputId::CreateServerUserData => write!(f, "CreateServerUserData"),
+            InputId::CreateServerBlueprint => write!(f, "CreateServerBlueprint"),
             InputId::Empty => write!(f, "Empty"),
         }
     }
@@ -76,6 +313,13 @@ impl FromStr for InputId {
             "CreateServerDatacenter" => Ok(InputId::CreateServerDatacenter),
             "CreateServerImage" => Ok(InputId::CreateServerImage),
             "CreateServerType" => Ok(InputId::CreateServerType),
+            "CreateServerLocation" => Ok(InputId::CreateServerLocation),
+            "CreateServerSshKeys" => Ok(InputId::CreateServerSshKeys),
+            "CreateServerPublicNet" => Ok(InputId::CreateServerPublicNet),
+            "CreateServerNetworks" => Ok(InputId::CreateServerNetworks),
+            "CreateServerFirewalls" => Ok(InputId::CreateServerFirewalls),
+            "CreateServerUserData" => Ok(InputId::CreateServerUserData),
+            "CreateServerBlueprint" => Ok(InputId::CreateServerBlueprint),
             "Empty" => Ok(InputId::Empty),
             _ => Err("Invalid input id".to_string()),
         }
@@ -92,6 +336,24 @@ pub enum Id {
     CreateServer1,
     CreateServer2,
     CreateServer3,
+    CreateServer4,
+    CreateServer5,
+    CreateServer6,
+    CreateServer7,
+    CreateServer8,
+    CreateServer9,
+    CreateServer10,
+    Blueprint,
+    UserDataPreview,
+    BatchProgress,
+    DeleteModal,
+    Prompt,
+    TaskPanel,
+    ActionLog,
+    ErrorPane,
+    Toasts,
+    NotificationPanel,
+    DebugLog,
 }
 
 pub enum Components {
@@ -101,6 +363,17 @@ pub enum Components {
     TextLabel(TextLabel),
     ServerListConnected(ServerListConnected),
     ServerListDisconnected(ServerListDisconnected),
+    DeleteModal(DeleteModal),
+    PromptModal(PromptModal),
+    Picker(Box<Picker>),
+    UserDataPreview(UserDataPreview),
+    BatchProgress(BatchProgress),
+    TaskPanel(TaskPanel),
+    ActionLog(ActionLog),
+    ErrorPane(ErrorPane),
+    Toasts(Toasts),
+    NotificationPanel(NotificationPanel),
+    DebugLog(DebugLog),
 }
 
 impl Components {
@@ -112,11 +385,23 @@ impl Components {
             Components::TextLabel(c) => Box::new(c),
             Components::ServerListConnected(c) => Box::new(c),
             Components::ServerListDisconnected(c) => Box::new(c),
+            Components::DeleteModal(c) => Box::new(c),
+            Components::Picker(c) => c,
+            Components::UserDataPreview(c) => Box::new(c),
+            Components::PromptModal(c) => Box::new(c),
+            Components::BatchProgress(c) => Box::new(c),
+            Components::TaskPanel(c) => Box::new(c),
+            Components::ActionLog(c) => Box::new(c),
+            Components::ErrorPane(c) => Box::new(c),
+            Components::Toasts(c) => Box::new(c),
+            Components::NotificationPanel(c) => Box::new(c),
+            Components::DebugLog(c) => Box::new(c),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Default)]
+#[derive(Debug, PartialEq, Clone, Eq, PartialOrd, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AuthPlatform {
     #[default]
     Google,
@@ -151,27 +436,181 @@ impl std::fmt::Display for AuthPlatform {
 pub struct Auth {
     pub auth: AuthPlatform,
     pub token: String,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
 }
 
 impl Auth {
-    pub fn new(auth: AuthPlatform, token: String) -> Self {
-        Self { auth, token }
+    pub fn new(
+        auth: AuthPlatform,
+        token: String,
+        region: Option<String>,
+        endpoint: Option<String>,
+    ) -> Self {
+        Self {
+            auth,
+            token,
+            region,
+            endpoint,
+        }
     }
 }
 
+/// Per-account values used when the create form leaves a field empty.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Default)]
-pub struct Config {
+pub struct Defaults {
+    pub image: Option<String>,
+    pub location: Option<String>,
+    pub server_type: Option<String>,
+    pub ssh_keys: Vec<String>,
+    pub user_data: Option<String>,
+    /// Where cloud-init templates are picked from
+    pub templates: Option<PathBuf>,
+}
+
+impl Defaults {
+    /// Fills the fields a create request leaves empty, loading the user data
+    /// from its template or file.
+    pub fn apply(&self, request: &mut CreateServer) -> Result<()> {
+        let fill = |value: &mut String, default: &Option<String>| {
+            if let Some(default) = default.as_ref().filter(|_| value.trim().is_empty()) {
+                *value = default.clone();
+            }
+        };
+        fill(&mut request.server_type, &self.server_type);
+        fill(&mut request.image, &self.image);
+        if request.location.is_none() {
+            request.location = self.location.clone();
+        }
+        if request.ssh_keys.is_empty() {
+            request.ssh_keys = self.ssh_keys.clone();
+        }
+        if request.user_data.is_none() {
+            request.user_data = self
+                .user_data
+                .as_deref()
+                .map(|reference| cloud_init::load(reference, self.templates.as_deref()))
+                .transpose()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Default)]
+pub struct Account {
+    pub name: String,
     pub auth: Auth,
+    pub defaults: Defaults,
 }
 
-impl Config {
-    pub fn new(args: Args) -> Self {
+impl Account {
+    pub fn new(name: String, auth: Auth) -> Self {
         Self {
-            auth: Auth::new(args.auth, args.token),
+            name,
+            auth,
+            defaults: Defaults::default(),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Default)]
+pub struct Config {
+    pub accounts: Vec<Account>,
+    pub blueprints: BTreeMap<String, Blueprint>,
+    /// Background refresh of the terminal UI, if any
+    pub refresh: Option<Duration>,
+}
+
+impl Config {
+    /// Builds the session accounts from the command line and the config file.
+    ///
+    /// Accounts come from `--profile`, `--auth` and `--account`; without any of
+    /// them the default profile, or else every profile, is loaded. Tokens resolve
+    /// as `--token`, then `token_command`, then `token`, then a stored token, then
+    /// the provider environment. `--region` and `--endpoint` override every account.
+    pub fn new(args: Args) -> Result<Self> {
+        let file = ConfigFile::load(args.config.as_deref())?;
+        let mut accounts = Vec::new();
+        let mut add = |account: Account| {
+            if accounts.iter().any(|a: &Account| a.name == account.name) {
+                bail!("Account {} is defined more than once", account.name);
+            }
+            accounts.push(account);
+            Ok(())
+        };
+
+        let profiles = if !args.profiles.is_empty() {
+            args.profiles
+        } else if args.auth.is_none() && args.accounts.is_empty() {
+            match file.default_profile.as_ref() {
+                Some(profile) => vec![profile.clone()],
+                None => file.profiles.keys().cloned().collect(),
+            }
+        } else {
+            Vec::new()
+        };
+
+        for name in profiles {
+            let profile = file.profile(&name)?;
+            add(Account {
+                auth: Auth::new(
+                    profile.provider.clone(),
+                    profile.token(&name)?,
+                    args.region.clone().or(profile.region.clone()),
+                    args.endpoint.clone().or(profile.endpoint.clone()),
+                ),
+                defaults: profile.defaults(),
+                name,
+            })?;
+        }
+
+        if let Some(auth) = args.auth {
+            let token = match args.token {
+                Some(token) => token,
+                None => config::env_token(&auth)?,
+            };
+            add(Account::new(
+                "default".to_string(),
+                Auth::new(auth, token, args.region.clone(), args.endpoint.clone()),
+            ))?;
+        }
+
+        for account in args.accounts {
+            let mut parts = account.splitn(3, ':');
+            let (Some(name), Some(auth), Some(token)) = (parts.next(), parts.next(), parts.next())
+            else {
+                bail!("Invalid account {}, expected NAME:PROVIDER:TOKEN", account);
+            };
+            let auth = auth
+                .parse::<AuthPlatform>()
+                .map_err(|err| anyhow!("{} for account {}", err, name))?;
+
+            add(Account::new(
+                name.to_string(),
+                Auth::new(
+                    auth,
+                    token.to_string(),
+                    args.region.clone(),
+                    args.endpoint.clone(),
+                ),
+            ))?;
+        }
+
+        if accounts.is_empty() {
+            bail!("No account configured, add a profile to the config file or pass --auth");
+        }
+        for account in accounts.iter_mut() {
+            account.defaults.templates = file.templates_dir();
+        }
+
+        Ok(Self {
+            accounts,
+            refresh: file.refresh_interval(),
+            blueprints: file.blueprints,
+        })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum State {
     SelectedServer(ServerHandle),
@@ -179,25 +618,31 @@ pub enum State {
     Empty,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ProviderStatus {
+    pub account: String,
+    #[serde(rename = "provider")]
     pub name: String,
     pub status: String,
     pub servers: usize,
     pub primary_ips: usize,
     pub firewalls: usize,
     pub load_balancers: usize,
+    /// API requests left, for providers that report a rate limit
+    pub quota: Option<Quota>,
 }
 
 impl Default for ProviderStatus {
     fn default() -> Self {
         Self {
+            account: String::new(),
             name: "Unknown".to_string(),
             status: "Disconnected".to_string(),
             servers: 0,
             primary_ips: 0,
             firewalls: 0,
             load_balancers: 0,
+            quota: None,
         }
     }
 }
@@ -210,18 +655,50 @@ impl ProviderStatus {
             ..Default::default()
         }
     }
+
+    pub fn is_connected(&self) -> bool {
+        self.status == "Connected"
+    }
+
+    /// Sums up the per-account statuses into a single overview.
+    pub fn aggregate(statuses: &[ProviderStatus]) -> Self {
+        let connected = statuses.iter().filter(|s| s.is_connected()).count();
+        Self {
+            account: String::new(),
+            name: format!("All accounts ({})", statuses.len()),
+            status: if connected == statuses.len() && connected > 0 {
+                "Connected".to_string()
+            } else if connected > 0 {
+                format!("Partially connected ({}/{})", connected, statuses.len())
+            } else {
+                "Disconnected".to_string()
+            },
+            servers: statuses.iter().map(|s| s.servers).sum(),
+            primary_ips: statuses.iter().map(|s| s.primary_ips).sum(),
+            firewalls: statuses.iter().map(|s| s.firewalls).sum(),
+            load_balancers: statuses.iter().map(|s| s.load_balancers).sum(),
+            // Every account has a quota of its own, the tightest one matters
+            quota: statuses
+                .iter()
+                .filter_map(|s| s.quota)
+                .min_by_key(|quota| quota.remaining),
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ServerStatus {
+    pub id: String,
     pub name: String,
+    pub account: String,
     pub status: String,
     pub ip: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ServerPreview {
     pub provider: String,
+    pub account: String,
     pub created_on: String,
     pub datacenter: String,
     pub image: String,
@@ -235,31 +712,29 @@ pub struct ServerPreview {
 
 impl ServerPreview {
     pub const fn count() -> usize {
-        10
+        11
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServerHandle {
-    Hetzner(Box<Server>),
+    Server(Box<Server>),
     Create,
 }
 
 impl ServerHandle {
     pub fn to_status(&self) -> Option<ServerStatus> {
         match self {
-            ServerHandle::Hetzner(server) => Some(ServerStatus {
+            ServerHandle::Server(server) => Some(ServerStatus {
+                id: server.id.clone(),
                 name: server.name.clone(),
-                status: if server.status == Status::Running {
-                    "Online".to_string()
-                } else {
-                    "Offline".to_string()
-                },
-                ip: if let Some(ipv4) = server.public_net.ipv4.as_ref() {
-                    ipv4.ip.to_string()
-                } else {
-                    "Private".to_string()
+                account: format!("{} ({})", server.account, server.provider),
+                status: match server.state {
+                    ServerState::Running => "Online".to_string(),
+                    ServerState::Off => "Offline".to_string(),
+                    state => format!("{}...", state),
                 },
+                ip: server.ipv4.clone().unwrap_or("Private".to_string()),
             }),
             _ => None,
         }
@@ -267,24 +742,21 @@ impl ServerHandle {
 
     pub fn to_preview(&self) -> Option<ServerPreview> {
         match self {
-            ServerHandle::Hetzner(server) => Some(ServerPreview {
-                provider: "Hetzner".to_string(),
+            ServerHandle::Server(server) => Some(ServerPreview {
+                provider: server.provider.to_string(),
+                account: server.account.clone(),
                 created_on: server.created.clone(),
-                datacenter: server.datacenter.name.clone(),
-                image: if let Some(image) = server.image.as_ref() {
-                    image.name.clone().unwrap_or("Unknown".to_string())
-                } else {
-                    "Unknown".to_string()
-                },
+                datacenter: server.location.clone(),
+                image: server.image.clone().unwrap_or("Unknown".to_string()),
                 tags: format!("{:?}", server.labels),
                 name: server.name.clone(),
                 traffic: (
-                    server.ingoing_traffic.unwrap_or(0) as f32 / 1024.0,
-                    server.outgoing_traffic.unwrap_or(0) as f32 / 1024.0,
+                    server.traffic.0 as f32 / 1024.0,
+                    server.traffic.1 as f32 / 1024.0,
                 ),
-                disk_size: server.primary_disk_size,
-                server_type: server.server_type.name.clone(),
-                status: format!("{:?}", server.status),
+                disk_size: server.disk_size,
+                server_type: server.server_type.clone(),
+                status: server.state.to_string(),
             }),
             _ => None,
         }
@@ -302,12 +774,40 @@ impl ServerListStatus {
     }
 }
 
+/// What an account offers for new servers, shown in the create pickers.
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    pub server_types: Vec<ServerType>,
+    pub images: Vec<Image>,
+    pub locations: Vec<Location>,
+    pub datacenters: Vec<Resource>,
+    pub ssh_keys: Vec<String>,
+    pub primary_ips: Vec<Resource>,
+    pub networks: Vec<Resource>,
+    pub firewalls: Vec<Resource>,
+    pub templates: Vec<Template>,
+    pub defaults: Defaults,
+}
+
 #[derive(Debug, Clone)]
 pub enum UserEvent {
-    ProviderStatus(ProviderStatus),
+    ProviderStatus(Vec<ProviderStatus>),
     ServerListStatus(ServerListStatus),
-    #[allow(dead_code)]
-    Error(String),
+    /// Servers listed so far while later pages are still loading
+    ServerPage(ServerListStatus),
+    CreateOptions(Box<CreateOptions>),
+    Error(Box<ErrorReport>),
+    /// Something a task did that the user should hear about
+    Notice(Severity, String),
+    /// Server name and error, if any, of a finished batch item
+    BatchProgress(String, Option<String>),
+    /// A task started, moved on or finished
+    TaskProgress(TaskInfo),
+    /// A provider action moved on or finished
+    ActionProgress(ActionInfo),
+    /// Provider statuses and servers from a background refresh, the servers
+    /// only when every account answered
+    Polled(Vec<ProviderStatus>, Option<ServerListStatus>),
     Refresh,
     Empty,
 }
@@ -322,6 +822,14 @@ impl PartialEq for UserEvent {
                     UserEvent::ServerListStatus(_)
                 )
                 | (UserEvent::Error(_), UserEvent::Error(_))
+                | (UserEvent::Notice(..), UserEvent::Notice(..))
+                | (UserEvent::CreateOptions(_), UserEvent::CreateOptions(_))
+                | (UserEvent::BatchProgress(..), UserEvent::BatchProgress(..))
+                | (UserEvent::TaskProgress(_), UserEvent::TaskProgress(_))
+                | (UserEvent::Polled(..), UserEvent::Polled(..))
+                | (UserEvent::ServerPage(_), UserEvent::ServerPage(_))
+                | (UserEvent::ActionProgress(_), UserEvent::ActionProgress(_))
+                | (UserEvent::Refresh, UserEvent::Refresh)
         )
     }
 }
@@ -357,3 +865,179 @@ impl UserEventIter {
         Self { events }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{lock_env, temp_dir};
+
+    const CONFIG: &str = r#"
+default_profile = "staging"
+
+[profiles.staging]
+provider = "hetzner"
+token = "staging-token"
+region = "fsn1"
+image = "fedora-41"
+ssh_keys = ["admin"]
+
+[profiles.prod]
+provider = "hetzner"
+token_command = "echo prod-token"
+
+[profiles.bare]
+provider = "hetzner"
+"#;
+
+    /// Builds the config for the given flags, with no stored tokens around and
+    /// `env` as the Hetzner token of the environment.
+    fn config_with_env(config: &str, flags: &[&str], env: Option<&str>) -> Result<Config> {
+        let dir = temp_dir();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, config).unwrap();
+        std::env::set_var("XDG_DATA_HOME", dir.join("data"));
+        match env {
+            Some(token) => std::env::set_var("HCLOUD_TOKEN", token),
+            None => std::env::remove_var("HCLOUD_TOKEN"),
+        }
+
+        let mut args = vec!["carton", "--config", path.to_str().unwrap()];
+        args.extend_from_slice(flags);
+        let config = Config::new(Args::try_parse_from(args)?);
+        std::fs::remove_dir_all(dir).unwrap();
+        config
+    }
+
+    fn config(config: &str, flags: &[&str]) -> Result<Config> {
+        config_with_env(config, flags, None)
+    }
+
+    fn names(config: &Config) -> Vec<&str> {
+        config
+            .accounts
+            .iter()
+            .map(|account| account.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn loads_default_profile() {
+        let _env = lock_env();
+        let config = config(CONFIG, &[]).unwrap();
+        assert_eq!(names(&config), ["staging"]);
+        let account = &config.accounts[0];
+        assert_eq!(account.auth.token, "staging-token");
+        assert_eq!(account.auth.region.as_deref(), Some("fsn1"));
+        assert_eq!(account.defaults.image.as_deref(), Some("fedora-41"));
+        assert_eq!(account.defaults.ssh_keys, ["admin"]);
+    }
+
+    #[test]
+    fn loads_every_profile_without_default() {
+        let _env = lock_env();
+        let content = CONFIG.replace("default_profile = \"staging\"", "");
+        let config = config_with_env(&content, &[], Some("env-token")).unwrap();
+        assert_eq!(names(&config), ["bare", "prod", "staging"]);
+        // Without any token in the file the provider environment is used
+        assert_eq!(config.accounts[0].auth.token, "env-token");
+    }
+
+    #[test]
+    fn profile_flags_select_accounts() {
+        let _env = lock_env();
+        let config = config(CONFIG, &["-p", "prod", "-p", "staging"]).unwrap();
+        assert_eq!(names(&config), ["prod", "staging"]);
+        assert_eq!(config.accounts[0].auth.token, "prod-token");
+    }
+
+    #[test]
+    fn flags_override_every_account() {
+        let _env = lock_env();
+        let config = config(
+            CONFIG,
+            &[
+                "-p",
+                "staging",
+                "--account",
+                "extra:hetzner:extra-token",
+                "--region",
+                "nbg1",
+                "--endpoint",
+                "http://localhost:8080",
+            ],
+        )
+        .unwrap();
+        assert_eq!(names(&config), ["staging", "extra"]);
+        for account in &config.accounts {
+            assert_eq!(account.auth.region.as_deref(), Some("nbg1"));
+            assert_eq!(
+                account.auth.endpoint.as_deref(),
+                Some("http://localhost:8080")
+            );
+        }
+        assert_eq!(config.accounts[1].auth.token, "extra-token");
+    }
+
+    #[test]
+    fn ad_hoc_account_skips_default_profile() {
+        let _env = lock_env();
+        let config = config(CONFIG, &["--auth", "hetzner", "--token", "flag-token"]).unwrap();
+        assert_eq!(names(&config), ["default"]);
+        assert_eq!(config.accounts[0].auth.token, "flag-token");
+        assert_eq!(config.accounts[0].auth.region, None);
+    }
+
+    #[test]
+    fn defaults_fill_empty_fields_and_load_user_data() {
+        let dir = temp_dir();
+        let file = dir.join("web.yaml");
+        std::fs::write(&file, "#cloud-config\npackages: [nginx]\n").unwrap();
+        let defaults = Defaults {
+            image: Some("fedora-41".to_string()),
+            server_type: Some("cx22".to_string()),
+            ssh_keys: vec!["admin".to_string()],
+            user_data: Some(file.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        let mut request = CreateServer {
+            name: "web-1".to_string(),
+            server_type: "cx32".to_string(),
+            ..Default::default()
+        };
+        defaults.apply(&mut request).unwrap();
+        assert_eq!(request.server_type, "cx32");
+        assert_eq!(request.image, "fedora-41");
+        assert_eq!(request.ssh_keys, ["admin"]);
+        assert_eq!(
+            request.user_data.as_deref(),
+            Some("#cloud-config\npackages: [nginx]\n")
+        );
+
+        // User data of the request itself is kept
+        let mut request = CreateServer {
+            user_data: Some("#cloud-config\n".to_string()),
+            ..Default::default()
+        };
+        defaults.apply(&mut request).unwrap();
+        assert_eq!(request.user_data.as_deref(), Some("#cloud-config\n"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        let mut request = CreateServer::default();
+        assert!(defaults.apply(&mut request).is_err());
+    }
+
+    #[test]
+    fn rejects_ambiguous_accounts() {
+        let _env = lock_env();
+        let both =
+            "[profiles.x]\nprovider = \"hetzner\"\ntoken = \"a\"\ntoken_command = \"echo b\"\n";
+        assert!(config(both, &["-p", "x"]).is_err());
+        assert!(config(CONFIG, &["-p", "bare"]).is_err());
+        assert!(config(CONFIG, &["-p", "missing"]).is_err());
+        assert!(config(CONFIG, &["-p", "prod", "--account", "prod:hetzner:x"]).is_err());
+        assert!(config(CONFIG, &["--account", "extra:digitalocean:x"]).is_err());
+        assert!(config(CONFIG, &["--account", "extra"]).is_err());
+        assert!(config("", &[]).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use age::secrecy::SecretString;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::xdg_dir;
+
+const SERVICE: &str = "carton";
+const PASSPHRASE_VAR: &str = "CARTON_PASSPHRASE";
+/// Whether the keyring has a native backend here, elsewhere it would only
+/// keep tokens in memory
+const KEYRING: bool = cfg!(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "macos",
+    target_os = "windows"
+));
+
+/// Where a stored token lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Keyring,
+    File,
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Keyring => write!(f, "keyring"),
+            Backend::File => write!(f, "encrypted file"),
+        }
+    }
+}
+
+/// Names of stored tokens and their backend, kept in plain text so listing
+/// never needs the keyring or the passphrase.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    tokens: BTreeMap<String, Backend>,
+}
+
+fn data_dir() -> Result<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").ok_or(anyhow!("Cannot locate a data directory"))
+}
+
+impl Index {
+    fn path() -> Result<PathBuf> {
+        Ok(data_dir()?.join("credentials.toml"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&path)?;
+        toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        std::fs::create_dir_all(data_dir()?)?;
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(SecretString::from(passphrase));
+    }
+
+    let passphrase = rpassword::prompt_password("Token file passphrase: ")?;
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        bail!("Passphrases do not match");
+    }
+    if passphrase.is_empty() {
+        bail!("Passphrase cannot be empty");
+    }
+    Ok(SecretString::from(passphrase))
+}
+
+/// Tokens encrypted with an age passphrase, used where no keyring is available.
+struct TokenFile;
+
+impl TokenFile {
+    fn path() -> Result<PathBuf> {
+        Ok(data_dir()?.join("tokens.age"))
+    }
+
+    fn read(passphrase: &SecretString) -> Result<BTreeMap<String, String>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let encrypted = std::fs::read(&path)?;
+        let identity = age::scrypt::Identity::new(passphrase.clone());
+        let mut reader = age::Decryptor::new(&encrypted[..])?
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .context("Cannot decrypt token file, wrong passphrase?")?;
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    fn write(passphrase: &SecretString, tokens: &BTreeMap<String, String>) -> Result<()> {
+        let encryptor = age::Encryptor::with_user_passphrase(passphrase.clone());
+        let mut encrypted = Vec::new();
+        let mut writer = encryptor.wrap_output(&mut encrypted)?;
+        writer.write_all(toml::to_string(tokens)?.as_bytes())?;
+        writer.finish()?;
+
+        std::fs::create_dir_all(data_dir()?)?;
+        std::fs::write(Self::path()?, encrypted)?;
+        Ok(())
+    }
+}
+
+/// Stores a token, preferring the keyring unless `file` forces the encrypted file.
+pub fn store(name: &str, token: &str, file: bool) -> Result<Backend> {
+    let mut index = Index::load()?;
+
+    let keyring = !file
+        && KEYRING
+        && keyring::Entry::new(SERVICE, name)
+            .and_then(|entry| entry.set_password(token))
+            .is_ok();
+    let backend = if keyring {
+        Backend::Keyring
+    } else {
+        let passphrase = passphrase(!TokenFile::path()?.exists())?;
+        let mut tokens = TokenFile::read(&passphrase)?;
+        tokens.insert(name.to_string(), token.to_string());
+        TokenFile::write(&passphrase, &tokens)?;
+        Backend::File
+    };
+
+    // A name lives in one backend only, drop a stale keyring copy
+    if backend == Backend::File && index.tokens.get(name) == Some(&Backend::Keyring) {
+        let _ = keyring::Entry::new(SERVICE, name).and_then(|entry| entry.delete_credential());
+    }
+
+    index.tokens.insert(name.to_string(), backend);
+    index.save()?;
+    Ok(backend)
+}
+
+/// Looks up a stored token, returning `None` if nothing was stored under `name`.
+pub fn load(name: &str) -> Result<Option<String>> {
+    match Index::load()?.tokens.get(name) {
+        None => Ok(None),
+        Some(Backend::Keyring) => {
+            let token = keyring::Entry::new(SERVICE, name)
+                .and_then(|entry| entry.get_password())
+                .with_context(|| format!("Cannot read token {} from the keyring", name))?;
+            Ok(Some(token))
+        }
+        Some(Backend::File) => {
+            let mut tokens = TokenFile::read(&passphrase(false)?)?;
+            Ok(tokens.remove(name))
+        }
+    }
+}
+
+pub fn remove(name: &str) -> Result<Backend> {
+    let mut index = Index::load()?;
+    let backend = index
+        .tokens
+        .remove(name)
+        .with_context(|| format!("No token stored for {}", name))?;
+
+    match backend {
+        Backend::Keyring => {
+            match keyring::Entry::new(SERVICE, name).and_then(|entry| entry.delete_credential()) {
+                Ok(()) | Err(keyring::Error::NoEntry) => {}
+                Err(err) => return Err(err).context("Cannot remove token from the keyring"),
+            }
+        }
+        Backend::File => {
+            let passphrase = passphrase(false)?;
+            let mut tokens = TokenFile::read(&passphrase)?;
+            tokens.remove(name);
+            TokenFile::write(&passphrase, &tokens)?;
+        }
+    }
+
+    index.save()?;
+    Ok(backend)
+}
+
+pub fn list() -> Result<Vec<(String, Backend)>> {
+    Ok(Index::load()?.tokens.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{lock_env, temp_dir};
+
+    // Every encryption takes about a second, age tunes scrypt to that
+    #[test]
+    fn file_round_trip() {
+        let _env = lock_env();
+        let dir = temp_dir();
+        std::env::set_var("XDG_DATA_HOME", &dir);
+        std::env::set_var(PASSPHRASE_VAR, "correct horse");
+
+        assert_eq!(load("prod").unwrap(), None);
+        assert_eq!(store("prod", "secret-1", true).unwrap(), Backend::File);
+        assert_eq!(store("staging", "secret-2", true).unwrap(), Backend::File);
+        assert_eq!(load("prod").unwrap().as_deref(), Some("secret-1"));
+        assert_eq!(
+            list().unwrap(),
+            [
+                ("prod".to_string(), Backend::File),
+                ("staging".to_string(), Backend::File)
+            ]
+        );
+        let content = std::fs::read(dir.join("carton").join("tokens.age")).unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("secret-1"));
+
+        assert_eq!(remove("prod").unwrap(), Backend::File);
+        assert_eq!(load("prod").unwrap(), None);
+        assert!(remove("prod").is_err());
+
+        std::env::set_var(PASSPHRASE_VAR, "wrong");
+        assert!(load("staging").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use reqwest::StatusCode;
+
+/// What went wrong, grouped by what the user can do about it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CartonError {
+    /// The provider rejected the credentials of an account
+    Auth(String),
+    NotFound(String),
+    RateLimited(String),
+    /// The input was refused, by carton or by the provider
+    Validation(String),
+    /// The provider could not be reached or took too long to answer
+    Network(String),
+    /// Any other failure the provider reported
+    Provider(String),
+}
+
+impl CartonError {
+    /// Sorts an error response by its HTTP status.
+    pub fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => CartonError::Auth(message),
+            StatusCode::NOT_FOUND => CartonError::NotFound(message),
+            StatusCode::TOO_MANY_REQUESTS => CartonError::RateLimited(message),
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                CartonError::Validation(message)
+            }
+            _ => CartonError::Provider(message),
+        }
+    }
+
+    /// Finds the typed error behind an error chain, anything untyped counts as
+    /// a provider failure.
+    pub fn classify(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<CartonError>() {
+                return err.clone();
+            }
+            if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+                if err.is_connect() || err.is_timeout() || err.is_request() {
+                    return CartonError::Network(format!("{:#}", err));
+                }
+            }
+        }
+        CartonError::Provider(format!("{:#}", err))
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            CartonError::Auth(_) => "Authentication failed",
+            CartonError::NotFound(_) => "Not found",
+            CartonError::RateLimited(_) => "Rate limited",
+            CartonError::Validation(_) => "Invalid input",
+            CartonError::Network(_) => "Network error",
+            CartonError::Provider(_) => "Provider error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CartonError::Auth(message)
+            | CartonError::NotFound(message)
+            | CartonError::RateLimited(message)
+            | CartonError::Validation(message)
+            | CartonError::Network(message)
+            | CartonError::Provider(message) => message,
+        }
+    }
+
+    /// A suggested fix to show next to the error.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            CartonError::Auth(_) => {
+                "Check the token of the profile, `carton auth add <profile>` stores a new one."
+            }
+            CartonError::NotFound(_) => {
+                "The resource may have been removed elsewhere, refresh the list and try again."
+            }
+            CartonError::RateLimited(_) => {
+                "Too many requests were made, wait a minute or raise refresh_interval."
+            }
+            CartonError::Validation(_) => "Correct the highlighted values and submit again.",
+            CartonError::Network(_) => {
+                "Check the network connection and the endpoint of the profile."
+            }
+            CartonError::Provider(_) => {
+                "See the details below, the provider status page may list an outage."
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for CartonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for CartonError {}
+
+/// A failed task as the terminal UI shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    /// What carton was doing, usually the task name
+    pub context: String,
+    pub error: CartonError,
+    /// The full error chain, for the error pane
+    pub details: String,
+}
+
+impl ErrorReport {
+    pub fn new(context: String, err: &anyhow::Error) -> Self {
+        Self {
+            context,
+            error: CartonError::classify(err),
+            details: format!("{:?}", err),
+        }
+    }
+}
+
+impl Default for ErrorReport {
+    fn default() -> Self {
+        Self {
+            context: String::new(),
+            error: CartonError::Provider(String::new()),
+            details: String::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use anyhow::Context;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+
+    #[test]
+    fn statuses_sort_errors() {
+        let error = |status| CartonError::from_status(status, "failed".to_string());
+        assert_eq!(
+            error(StatusCode::UNAUTHORIZED).title(),
+            "Authentication failed"
+        );
+        assert_eq!(
+            error(StatusCode::FORBIDDEN).title(),
+            "Authentication failed"
+        );
+        assert_eq!(error(StatusCode::NOT_FOUND).title(), "Not found");
+        assert_eq!(error(StatusCode::TOO_MANY_REQUESTS).title(), "Rate limited");
+        assert_eq!(error(StatusCode::BAD_REQUEST).title(), "Invalid input");
+        assert_eq!(
+            error(StatusCode::UNPROCESSABLE_ENTITY).title(),
+            "Invalid input"
+        );
+        assert_eq!(
+            error(StatusCode::SERVICE_UNAVAILABLE),
+            CartonError::Provider("failed".to_string())
+        );
+    }
+
+    #[test]
+    fn classify_finds_typed_error_behind_context() {
+        let err = anyhow::Error::from(CartonError::NotFound("server 7".to_string()))
+            .context("Cannot delete server")
+            .context("Delete web-1");
+        assert_eq!(
+            CartonError::classify(&err),
+            CartonError::NotFound("server 7".to_string())
+        );
+        assert_eq!(
+            ErrorReport::new("Delete web-1".to_string(), &err).to_string(),
+            "Delete web-1: server 7"
+        );
+    }
+
+    #[test]
+    fn classify_counts_untyped_errors_as_provider_failures() {
+        let err = Err::<(), _>(anyhow::anyhow!("Unexpected response"))
+            .context("Cannot list servers")
+            .unwrap_err();
+        assert_eq!(
+            CartonError::classify(&err),
+            CartonError::Provider("Cannot list servers: Unexpected response".to_string())
+        );
+    }
+
+    #[test]
+    fn classify_counts_unreachable_hosts_as_network_errors() {
+        // Nothing listens on a port that was just given back
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(reqwest::get(format!("http://127.0.0.1:{}", port)))
+            .unwrap_err();
+        let err = anyhow::Error::from(err).context("Cannot fetch servers");
+        assert!(matches!(
+            CartonError::classify(&err),
+            CartonError::Network(_)
+        ));
+    }
+}
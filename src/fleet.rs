@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::constants::AuthPlatform;
+use crate::providers::{CreateServer, Server, UpdateServer};
+use crate::validation;
+
+/// Label carrying the fleet name, servers missing from the file are only
+/// deleted when they have it.
+pub const LABEL: &str = "carton-fleet";
+
+/// Servers an account should run, read from a YAML fleet file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fleet {
+    pub name: String,
+    #[serde(default)]
+    pub servers: Vec<FleetServer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetServer {
+    pub name: String,
+    #[serde(alias = "type")]
+    pub server_type: String,
+    pub image: String,
+    /// Left to the profile default when unset, and then never compared
+    pub location: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl Fleet {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read fleet file {}", path.display()))?;
+        let fleet: Fleet = serde_yaml::from_str(&content)
+            .with_context(|| format!("Invalid fleet file {}", path.display()))?;
+
+        validation::hostname(&fleet.name).context("Invalid fleet name")?;
+        let mut names = BTreeSet::new();
+        for server in &fleet.servers {
+            validation::hostname(&server.name)
+                .with_context(|| format!("Invalid server name {:?}", server.name))?;
+            if !names.insert(&server.name) {
+                bail!("Server {} is listed twice", server.name);
+            }
+        }
+        Ok(fleet)
+    }
+
+    /// Compares the fleet with the servers of its account, listed servers come
+    /// first in file order, deletions last.
+    pub fn plan(&self, servers: &[Server]) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for wanted in &self.servers {
+            let mut labels = wanted.labels.clone();
+            labels.insert(LABEL.to_string(), self.name.clone());
+            let request = CreateServer {
+                name: wanted.name.clone(),
+                server_type: wanted.server_type.clone(),
+                image: wanted.image.clone(),
+                location: wanted.location.clone(),
+                enable_ipv6: true,
+                labels: labels.clone(),
+                ..Default::default()
+            };
+
+            let Some(server) = servers.iter().find(|server| server.name == wanted.name) else {
+                changes.push(Change::Create(Box::new(request)));
+                continue;
+            };
+
+            // Images and locations cannot change in place
+            let mut diffs = Vec::new();
+            if let Some(image) =
+                comparable_image(server, &wanted.image).filter(|image| *image != wanted.image)
+            {
+                diffs.push(Diff::new("image", image, &wanted.image));
+            }
+            if let Some(location) = wanted
+                .location
+                .as_ref()
+                .filter(|location| !server.location.starts_with(location.as_str()))
+            {
+                diffs.push(Diff::new("location", &server.location, location));
+            }
+            if !diffs.is_empty() {
+                changes.push(Change::Replace(
+                    Box::new(server.clone()),
+                    Box::new(request),
+                    diffs,
+                ));
+                continue;
+            }
+
+            let mut update = UpdateServer::default();
+            if server.server_type != wanted.server_type {
+                diffs.push(Diff::new(
+                    "server_type",
+                    &server.server_type,
+                    &wanted.server_type,
+                ));
+                update.server_type = Some(wanted.server_type.clone());
+            }
+            if server.labels != labels {
+                diffs.push(Diff::new(
+                    "labels",
+                    &format_labels(&server.labels),
+                    &format_labels(&labels),
+                ));
+                update.labels = Some(labels);
+            }
+            if !diffs.is_empty() {
+                changes.push(Change::Update(
+                    Box::new(server.clone()),
+                    Box::new(update),
+                    diffs,
+                ));
+            }
+        }
+
+        for server in servers {
+            let managed = server.labels.get(LABEL) == Some(&self.name);
+            if managed && !self.servers.iter().any(|wanted| wanted.name == server.name) {
+                changes.push(Change::Delete(Box::new(server.clone())));
+            }
+        }
+
+        changes
+    }
+}
+
+/// One field a plan changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff {
+    pub field: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+impl Diff {
+    fn new(field: &'static str, from: &str, to: &str) -> Self {
+        Self {
+            field,
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    Create(Box<CreateServer>),
+    /// Changed in place, a new type needs the server powered off
+    Update(Box<Server>, Box<UpdateServer>, Vec<Diff>),
+    /// Deleted and created again
+    Replace(Box<Server>, Box<CreateServer>, Vec<Diff>),
+    Delete(Box<Server>),
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let diffs = |diffs: &[Diff]| {
+            diffs
+                .iter()
+                .map(|diff| format!("{} {} -> {}", diff.field, diff.from, diff.to))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Change::Create(request) => write!(
+                f,
+                "+   create  {} ({}, {}, {})",
+                request.name,
+                request.server_type,
+                request.image,
+                request.location.as_deref().unwrap_or("default location")
+            ),
+            Change::Update(server, update, changes) => {
+                write!(f, "~   change  {}: {}", server.name, diffs(changes))?;
+                if update.server_type.is_some() {
+                    write!(f, " (shuts the server down)")?;
+                }
+                Ok(())
+            }
+            Change::Replace(server, _, changes) => {
+                write!(f, "-/+ replace {}: {}", server.name, diffs(changes))
+            }
+            Change::Delete(server) => write!(f, "-   delete  {}", server.name),
+        }
+    }
+}
+
+/// Finds what would keep the creates of a plan from succeeding, so nothing is
+/// changed for a plan that cannot be carried out. Images are left to the
+/// provider, fleets may name public ones it does not list.
+pub fn check(changes: &[Change], server_types: &[String]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for change in changes {
+        let (Change::Create(request) | Change::Replace(_, request, _)) = change else {
+            continue;
+        };
+        let images = [request.image.clone()];
+        for (_, message) in validation::create_server(request, server_types, &images, &[]) {
+            errors.push(format!("{}: {}", request.name, message));
+        }
+    }
+    errors
+}
+
+/// The listed image of a server when it can be compared with `wanted`, a
+/// mismatch then means the server runs another image. Google only lists the
+/// boot disk license, e.g. `debian-12-bookworm` for any image of the
+/// `debian-12` family, and Hetzner lists names while a fleet may give an id.
+fn comparable_image<'a>(server: &'a Server, wanted: &str) -> Option<&'a str> {
+    if wanted.is_empty() {
+        // The provider default is not known up front
+        return None;
+    }
+    match server.provider {
+        AuthPlatform::Google => None,
+        AuthPlatform::Hetzner if wanted.bytes().all(|b| b.is_ascii_digit()) => None,
+        AuthPlatform::Amazon | AuthPlatform::Hetzner => server.image.as_deref(),
+    }
+}
+
+fn format_labels(labels: &BTreeMap<String, String>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fleet() -> Fleet {
+        serde_yaml::from_str(
+            "
+name: web
+servers:
+  - name: web-1
+    type: cx22
+    image: fedora-41
+    location: fsn1
+    labels: {role: web}
+  - name: web-2
+    type: cx22
+    image: fedora-41
+",
+        )
+        .unwrap()
+    }
+
+    fn server(name: &str, provider: AuthPlatform, image: &str) -> Server {
+        Server {
+            id: name.to_string(),
+            name: name.to_string(),
+            provider,
+            location: "fsn1-dc14".to_string(),
+            image: Some(image.to_string()),
+            server_type: "cx22".to_string(),
+            labels: BTreeMap::from([(LABEL.to_string(), "web".to_string())]),
+            ..Default::default()
+        }
+    }
+
+    fn managed(name: &str) -> Server {
+        let mut server = server(name, AuthPlatform::Hetzner, "fedora-41");
+        if name == "web-1" {
+            server.labels.insert("role".to_string(), "web".to_string());
+        }
+        server
+    }
+
+    #[test]
+    fn empty_listing_creates_everything() {
+        let changes = fleet().plan(&[]);
+        assert_eq!(changes.len(), 2);
+        let Change::Create(request) = &changes[0] else {
+            panic!("{}", changes[0]);
+        };
+        assert_eq!(request.name, "web-1");
+        assert_eq!(request.location.as_deref(), Some("fsn1"));
+        assert_eq!(request.labels[LABEL], "web");
+        assert_eq!(request.labels["role"], "web");
+        assert!(matches!(&changes[1], Change::Create(request) if request.name == "web-2"));
+    }
+
+    #[test]
+    fn matching_servers_plan_nothing() {
+        assert!(fleet()
+            .plan(&[managed("web-1"), managed("web-2")])
+            .is_empty());
+    }
+
+    #[test]
+    fn type_and_labels_change_in_place() {
+        let mut resized = managed("web-2");
+        resized.server_type = "cx32".to_string();
+        resized.labels.insert("env".to_string(), "dev".to_string());
+        let changes = fleet().plan(&[managed("web-1"), resized]);
+        let [Change::Update(server, update, diffs)] = changes.as_slice() else {
+            panic!("{:?}", changes);
+        };
+        assert_eq!(server.name, "web-2");
+        assert_eq!(update.server_type.as_deref(), Some("cx22"));
+        assert_eq!(
+            update.labels,
+            Some(BTreeMap::from([(LABEL.to_string(), "web".to_string())]))
+        );
+        assert_eq!(
+            diffs.iter().map(|diff| diff.field).collect::<Vec<_>>(),
+            ["server_type", "labels"]
+        );
+    }
+
+    #[test]
+    fn image_and_location_replace() {
+        let mut moved = managed("web-1");
+        moved.location = "nbg1-dc3".to_string();
+        let changes = fleet().plan(&[
+            moved,
+            server("web-2", AuthPlatform::Hetzner, "ubuntu-24.04"),
+        ]);
+        assert_eq!(changes.len(), 2);
+        for (change, field) in changes.iter().zip(["location", "image"]) {
+            let Change::Replace(_, _, diffs) = change else {
+                panic!("{}", change);
+            };
+            assert_eq!(diffs[0].field, field);
+        }
+    }
+
+    #[test]
+    fn unconfirmed_images_do_not_replace() {
+        let mut fleet = fleet();
+        fleet.servers[1].image = "projects/debian-cloud/global/images/family/debian-12".to_string();
+        let google = server("web-2", AuthPlatform::Google, "debian-12-bookworm");
+        assert!(fleet.plan(&[managed("web-1"), google]).is_empty());
+
+        fleet.servers[1].image = "114690387".to_string();
+        assert!(fleet.plan(&[managed("web-1"), managed("web-2")]).is_empty());
+
+        fleet.servers[1].image = String::new();
+        assert!(fleet.plan(&[managed("web-1"), managed("web-2")]).is_empty());
+    }
+
+    #[test]
+    fn check_reports_creates_that_would_fail() {
+        let server_types = ["cx22".to_string()];
+        let mut fleet = fleet();
+        assert!(check(&fleet.plan(&[]), &server_types).is_empty());
+
+        fleet.servers[0].server_type = "cx99".to_string();
+        fleet.servers[1].image = "custom-image".to_string();
+        let changes = fleet.plan(&[managed("web-2")]);
+        assert!(matches!(changes[1], Change::Replace(..)));
+        // Unlisted images are left to the provider
+        assert_eq!(
+            check(&changes, &server_types),
+            ["web-1: Unknown server type \"cx99\""]
+        );
+    }
+
+    #[test]
+    fn only_managed_servers_are_deleted() {
+        let mut other = managed("db-1");
+        other.labels.insert(LABEL.to_string(), "db".to_string());
+        let mut unlabeled = managed("mail-1");
+        unlabeled.labels.clear();
+        let changes = fleet().plan(&[
+            managed("web-1"),
+            managed("web-2"),
+            managed("web-3"),
+            other,
+            unlabeled,
+        ]);
+        let [Change::Delete(server)] = changes.as_slice() else {
+            panic!("{:?}", changes);
+        };
+        assert_eq!(server.name, "web-3");
+    }
+}
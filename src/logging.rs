@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use clap::ValueEnum;
+use tracing_appender::rolling::{Builder, RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::xdg_dir;
+
+/// Daily log files kept before the oldest is removed
+const LOG_FILES: usize = 7;
+/// Log lines kept in memory for the debug overlay
+const RECENT: usize = 500;
+
+static LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Local time in the given `chrono` format.
+struct Clock(&'static str);
+
+impl FormatTime for Clock {
+    fn format_time(&self, w: &mut Writer<'_>) -> std::fmt::Result {
+        write!(w, "{}", Local::now().format(self.0))
+    }
+}
+
+/// Keeps the latest lines for the debug overlay, the formatter hands over
+/// one event per write.
+struct Recent;
+
+impl Write for Recent {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut lines = LINES.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines() {
+            if lines.len() >= RECENT {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+        WRITES.fetch_add(1, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Directory of the log files, `$XDG_STATE_HOME/carton`.
+pub fn log_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Opens the daily rotated log file in `dir`, removing the oldest files past
+/// `LOG_FILES`.
+fn log_file(dir: &Path) -> Result<RollingFileAppender> {
+    // The appender prunes old files before it creates the directory
+    std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("carton")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES)
+        .build(dir)
+        .with_context(|| format!("Cannot open a log file in {}", dir.display()))
+}
+
+/// Lets carton's events through from `level` up. Libraries only get to
+/// report problems, their debug output drowns carton's.
+fn filter(level: LogLevel) -> Targets {
+    let level = LevelFilter::from(level);
+    Targets::new()
+        .with_default(level.min(LevelFilter::WARN))
+        .with_target("carton", level)
+}
+
+/// Sends events at `level` and above to a daily rotated file in the log
+/// directory and to the debug overlay. The overlay keeps working when the
+/// file cannot be opened.
+pub fn init(level: LogLevel) -> Result<()> {
+    let file = log_dir()
+        .ok_or(anyhow!("Cannot locate a state directory"))
+        .and_then(|dir| log_file(&dir));
+    let (file, err) = match file {
+        Ok(file) => (Some(file), None),
+        Err(err) => (None, Some(err)),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter(level))
+        .with(file.map(|file| {
+            tracing_subscriber::fmt::layer()
+                .with_timer(Clock("%Y-%m-%dT%H:%M:%S%.3f%:z"))
+                .with_writer(file)
+        }))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_timer(Clock("%H:%M:%S"))
+                .with_target(false)
+                .with_writer(|| Recent),
+        )
+        .init();
+
+    match err {
+        Some(err) => {
+            tracing::warn!("{:#}", err);
+            Err(err)
+        }
+        None => Ok(()),
+    }
+}
+
+/// The latest log lines, oldest first.
+pub fn recent() -> Vec<String> {
+    LINES.lock().unwrap().iter().cloned().collect()
+}
+
+/// How many events were logged so far, tells when `recent` changed.
+pub fn writes() -> usize {
+    WRITES.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::Level;
+
+    use super::*;
+    use crate::testing::temp_dir;
+
+    #[test]
+    fn libraries_only_report_problems() {
+        let debug = filter(LogLevel::Debug);
+        assert!(debug.would_enable("carton::app::tasks", &Level::DEBUG));
+        assert!(!debug.would_enable("carton::app::tasks", &Level::TRACE));
+        assert!(!debug.would_enable("hyper", &Level::INFO));
+        assert!(debug.would_enable("hyper", &Level::WARN));
+
+        let error = filter(LogLevel::Error);
+        assert!(!error.would_enable("carton", &Level::WARN));
+        assert!(!error.would_enable("hyper", &Level::WARN));
+        assert!(error.would_enable("hyper", &Level::ERROR));
+
+        let off = filter(LogLevel::Off);
+        assert!(!off.would_enable("carton", &Level::ERROR));
+        assert!(!off.would_enable("hyper", &Level::ERROR));
+    }
+
+    #[test]
+    fn log_file_rotates_daily_and_prunes_old_files() {
+        let state = temp_dir();
+        let dir = state.join("carton");
+        std::fs::create_dir_all(&dir).unwrap();
+        for day in 1..=LOG_FILES + 2 {
+            std::fs::write(dir.join(format!("carton.2024-01-{:02}.log", day)), "").unwrap();
+        }
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut file = log_file(&dir).unwrap();
+        file.write_all(b"started\n").unwrap();
+        file.flush().unwrap();
+
+        let today = format!("carton.{}.log", Local::now().format("%Y-%m-%d"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&today)).unwrap(),
+            "started\n"
+        );
+        let mut logs = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".log"))
+            .collect::<Vec<_>>();
+        logs.sort();
+        // Older files make room for today's, which goes by creation time
+        assert_eq!(logs.len(), LOG_FILES, "{:?}", logs);
+        assert!(logs.contains(&today), "{:?}", logs);
+        assert!(dir.join("notes.txt").exists());
+
+        // A missing directory is created
+        std::fs::remove_dir_all(&state).unwrap();
+        assert!(log_file(&dir).is_ok());
+        std::fs::remove_dir_all(state).unwrap();
+    }
+
+    #[test]
+    fn overlay_keeps_latest_lines() {
+        let writes = super::writes();
+        for index in 0..RECENT {
+            Recent
+                .write_all(format!("line {}", index).as_bytes())
+                .unwrap();
+        }
+        // One event per write, however many lines it spans
+        Recent.write_all(b"last\nlines\n").unwrap();
+        assert_eq!(super::writes(), writes + RECENT + 1);
+        let lines = recent();
+        assert_eq!(lines.len(), RECENT);
+        assert_eq!(lines[0], "line 2");
+        assert_eq!(lines[RECENT - 2..], ["last", "lines"]);
+
+        let subscriber = tracing_subscriber::registry()
+            .with(filter(LogLevel::Info))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_target(false)
+                    .with_ansi(false)
+                    .with_writer(|| Recent),
+            );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("left out");
+            tracing::info!("Created web-1");
+        });
+        assert_eq!(super::writes(), writes + RECENT + 2);
+        let line = recent().pop().unwrap();
+        assert!(line.ends_with("INFO Created web-1"), "{}", line);
+    }
+}
@@ -3,13 +3,49 @@ use clap::Parser;
 use tuirealm::{application::PollStrategy, Update};
 
 mod app;
+mod cli;
+mod cloud_init;
 mod components;
+mod config;
 mod constants;
+mod credentials;
+mod error;
+mod fleet;
+mod logging;
+mod providers;
+#[cfg(test)]
+mod testing;
+mod validation;
 
 fn main() {
-    let args = constants::Args::parse();
+    let mut args = constants::Args::parse();
+    if let Err(err) = logging::init(args.log_level) {
+        eprintln!("Warning: {:#}", err);
+    }
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "carton started");
+
+    if let Some(command) = args.command.take() {
+        if let Err(err) = cli::run(args, command) {
+            tracing::error!("{:#}", err);
+            eprintln!("Error: {:#}", err);
+            if let Some(err) = err
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<error::CartonError>())
+            {
+                eprintln!("Hint: {}", err.hint());
+            }
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let mut model = Model::new(args);
+    let mut model = match Model::new(args) {
+        Ok(model) => model,
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            std::process::exit(1);
+        }
+    };
     let _ = model.terminal.enter_alternate_screen();
     let _ = model.terminal.enable_raw_mode();
 
@@ -28,6 +64,7 @@ fn main() {
                         msg = model.update(msg);
                     }
                 }
+                model.pause_refresh();
             }
             _ => {}
         }
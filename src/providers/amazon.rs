@@ -0,0 +1,849 @@
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{sign, SignableBody, SignableRequest, SigningSettings};
+use aws_sigv4::sign::v4;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::constants::{Auth, AuthPlatform, ProviderStatus};
+use crate::error::CartonError;
+use crate::providers::http::Http;
+use crate::providers::{
+    CloudProvider, CreateServer, Image, Location, PowerAction, Server, ServerState, ServerType,
+};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const LIVE_STATES: [&str; 5] = ["pending", "running", "stopping", "stopped", "shutting-down"];
+/// Items per page of the EC2 Describe calls, EC2 only pages when asked to
+const PAGE_SIZE: &str = "100";
+
+/// AWS query-protocol service carton talks to.
+#[derive(Debug, Clone, Copy)]
+enum Service {
+    Ec2,
+    LoadBalancing,
+}
+
+impl Service {
+    fn name(&self) -> &'static str {
+        match self {
+            Service::Ec2 => "ec2",
+            Service::LoadBalancing => "elasticloadbalancing",
+        }
+    }
+
+    fn version(&self) -> &'static str {
+        match self {
+            Service::Ec2 => "2016-11-15",
+            Service::LoadBalancing => "2015-12-01",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(bound = "T: Deserialize<'de>")]
+struct ItemSet<T> {
+    #[serde(default, rename = "item")]
+    items: Vec<T>,
+}
+
+impl<T> Default for ItemSet<T> {
+    fn default() -> Self {
+        Self { items: Vec::new() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Tag {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstanceState {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Placement {
+    #[serde(default)]
+    availability_zone: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Instance {
+    instance_id: String,
+    #[serde(default)]
+    image_id: Option<String>,
+    instance_state: InstanceState,
+    instance_type: String,
+    #[serde(default)]
+    launch_time: String,
+    placement: Placement,
+    #[serde(default)]
+    ip_address: Option<String>,
+    #[serde(default)]
+    ipv6_address: Option<String>,
+    #[serde(default)]
+    tag_set: ItemSet<Tag>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reservation {
+    #[serde(default)]
+    instances_set: ItemSet<Instance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeInstancesResponse {
+    #[serde(default)]
+    reservation_set: ItemSet<Reservation>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RunInstancesResponse {
+    instances_set: ItemSet<Instance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeAddressesResponse {
+    #[serde(default)]
+    addresses_set: ItemSet<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeSecurityGroupsResponse {
+    #[serde(default)]
+    security_group_info: ItemSet<serde::de::IgnoredAny>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyPair {
+    key_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeKeyPairsResponse {
+    #[serde(default)]
+    key_set: ItemSet<KeyPair>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VCpuInfo {
+    default_v_cpus: i32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MemoryInfo {
+    size_in_mi_b: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceStorageInfo {
+    total_size_in_g_b: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceTypeInfo {
+    instance_type: String,
+    v_cpu_info: VCpuInfo,
+    memory_info: MemoryInfo,
+    #[serde(default)]
+    instance_storage_info: Option<InstanceStorageInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeInstanceTypesResponse {
+    #[serde(default)]
+    instance_type_set: ItemSet<InstanceTypeInfo>,
+    next_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageInfo {
+    image_id: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeImagesResponse {
+    #[serde(default)]
+    images_set: ItemSet<ImageInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AvailabilityZone {
+    zone_name: String,
+    #[serde(default)]
+    region_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DescribeAvailabilityZonesResponse {
+    #[serde(default)]
+    availability_zone_info: ItemSet<AvailabilityZone>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Members {
+    #[serde(default, rename = "member")]
+    members: Vec<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeLoadBalancersResult {
+    load_balancers: Option<Members>,
+    next_marker: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DescribeLoadBalancersResponse {
+    describe_load_balancers_result: DescribeLoadBalancersResult,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiErrors {
+    #[serde(default, rename = "Error")]
+    errors: Vec<ApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    #[serde(default)]
+    errors: Option<ApiErrors>,
+    #[serde(default)]
+    error: Option<ApiError>,
+}
+
+impl From<Instance> for Server {
+    fn from(instance: Instance) -> Self {
+        let mut name = instance.instance_id.clone();
+        let labels = instance
+            .tag_set
+            .items
+            .into_iter()
+            .filter_map(|tag| {
+                if tag.key == "Name" {
+                    name = tag.value;
+                    None
+                } else {
+                    Some((tag.key, tag.value))
+                }
+            })
+            .collect();
+
+        Self {
+            id: instance.instance_id,
+            name,
+            provider: AuthPlatform::Amazon,
+            state: match instance.instance_state.name.as_str() {
+                "pending" => ServerState::Starting,
+                "running" => ServerState::Running,
+                "stopping" => ServerState::Stopping,
+                "stopped" => ServerState::Off,
+                "shutting-down" | "terminated" => ServerState::Deleting,
+                _ => ServerState::Unknown,
+            },
+            ipv4: instance.ip_address,
+            ipv6: instance.ipv6_address,
+            created: instance.launch_time,
+            location: instance.placement.availability_zone,
+            image: instance.image_id,
+            labels,
+            server_type: instance.instance_type,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Amazon {
+    http: Http,
+    credentials: Credentials,
+    region: String,
+    endpoint: Option<String>,
+}
+
+impl Amazon {
+    /// Builds the backend from an `ACCESS_KEY_ID:SECRET_ACCESS_KEY[:SESSION_TOKEN]` token.
+    pub fn new(auth: &Auth) -> Result<Self> {
+        let mut parts = auth.token.splitn(3, ':');
+        let (Some(key_id), Some(secret)) = (parts.next(), parts.next()) else {
+            bail!("Amazon token must be ACCESS_KEY_ID:SECRET_ACCESS_KEY");
+        };
+        let session = parts.next().map(|s| s.to_string());
+
+        let region = auth
+            .region
+            .clone()
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+            .unwrap_or(DEFAULT_REGION.to_string());
+
+        Ok(Self {
+            http: Http::default(),
+            credentials: Credentials::new(key_id, secret, session, None, "carton"),
+            region,
+            endpoint: auth.endpoint.clone(),
+        })
+    }
+
+    fn endpoint(&self, service: Service) -> String {
+        match self.endpoint.as_ref() {
+            Some(endpoint) => format!("{}/", endpoint.trim_end_matches('/')),
+            None => format!("https://{}.{}.amazonaws.com/", service.name(), self.region),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        service: Service,
+        action: &str,
+        params: &[(String, String)],
+    ) -> Result<T> {
+        let mut form = vec![
+            ("Action".to_string(), action.to_string()),
+            ("Version".to_string(), service.version().to_string()),
+        ];
+        form.extend_from_slice(params);
+        let body = serde_urlencoded::to_string(&form)?;
+
+        let url = self.endpoint(service);
+        let content_type = "application/x-www-form-urlencoded; charset=utf-8";
+        let identity = self.credentials.clone().into();
+        let signing_params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(&self.region)
+            .name(service.name())
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()?
+            .into();
+        let signable = SignableRequest::new(
+            "POST",
+            url.as_str(),
+            [("content-type", content_type)].into_iter(),
+            SignableBody::Bytes(body.as_bytes()),
+        )?;
+        let (instructions, _) = sign(signable, &signing_params)?.into_parts();
+
+        let mut request = self
+            .http
+            .client()
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, content_type);
+        for (name, value) in instructions.headers() {
+            request = request.header(name, value);
+        }
+
+        // Describe calls only read, every other action changes something
+        let idempotent = action.starts_with("Describe");
+        let resp = self.http.send(request.body(body), idempotent).await?;
+        let status = resp.status();
+        let content = resp.text().await?;
+
+        if status.is_success() {
+            quick_xml::de::from_str(&content)
+                .with_context(|| format!("Unexpected {} response", action))
+        } else {
+            let error = quick_xml::de::from_str::<ErrorResponse>(&content)
+                .ok()
+                .and_then(|resp| {
+                    resp.error
+                        .or_else(|| resp.errors.and_then(|e| e.errors.into_iter().next()))
+                });
+            let Some(error) = error else {
+                let message = format!("{} failed with status {}", action, status);
+                return Err(CartonError::from_status(status, message).into());
+            };
+            let message = format!("{}: {} ({})", error.code, error.message, status);
+            // EC2 answers most failures with a 400, the code tells them apart
+            Err(match error.code.as_str() {
+                code if code.ends_with("NotFound") => CartonError::NotFound(message),
+                "AuthFailure"
+                | "UnauthorizedOperation"
+                | "InvalidClientTokenId"
+                | "SignatureDoesNotMatch" => CartonError::Auth(message),
+                "RequestLimitExceeded" | "Throttling" => CartonError::RateLimited(message),
+                _ => CartonError::from_status(status, message),
+            }
+            .into())
+        }
+    }
+
+    /// Points the params of a Describe call at the page after the last one.
+    fn set_token(params: &mut Vec<(String, String)>, token: String) {
+        params.retain(|(name, _)| name != "NextToken");
+        params.push(("NextToken".to_string(), token));
+    }
+
+    fn instance_params(id: &str) -> Vec<(String, String)> {
+        vec![("InstanceId.1".to_string(), id.to_string())]
+    }
+}
+
+#[async_trait]
+impl CloudProvider for Amazon {
+    fn platform(&self) -> AuthPlatform {
+        AuthPlatform::Amazon
+    }
+
+    async fn status(&self) -> Result<ProviderStatus> {
+        let mut overview = ProviderStatus::new(self.platform());
+
+        overview.servers = self.list_servers().await?.len();
+        overview.primary_ips = self
+            .call::<DescribeAddressesResponse>(Service::Ec2, "DescribeAddresses", &[])
+            .await?
+            .addresses_set
+            .items
+            .len();
+
+        let mut params = vec![("MaxResults".to_string(), PAGE_SIZE.to_string())];
+        loop {
+            let resp: DescribeSecurityGroupsResponse = self
+                .call(Service::Ec2, "DescribeSecurityGroups", &params)
+                .await?;
+            overview.firewalls += resp.security_group_info.items.len();
+            match resp.next_token {
+                Some(token) => Self::set_token(&mut params, token),
+                None => break,
+            }
+        }
+
+        // Load balancing pages with a marker instead of a token
+        let mut params = Vec::new();
+        loop {
+            let resp = self
+                .call::<DescribeLoadBalancersResponse>(
+                    Service::LoadBalancing,
+                    "DescribeLoadBalancers",
+                    &params,
+                )
+                .await?
+                .describe_load_balancers_result;
+            overview.load_balancers += resp.load_balancers.map_or(0, |lbs| lbs.members.len());
+            match resp.next_marker {
+                Some(marker) => params = vec![("Marker".to_string(), marker)],
+                None => break,
+            }
+        }
+
+        overview.quota = self.http.quota();
+        Ok(overview)
+    }
+
+    async fn list_server_pages(&self, page: &(dyn Fn(Vec<Server>) + Send + Sync)) -> Result<()> {
+        // Terminated instances linger in listings for a while, skip them
+        let mut params = vec![(
+            "Filter.1.Name".to_string(),
+            "instance-state-name".to_string(),
+        )];
+        for (index, state) in LIVE_STATES.iter().enumerate() {
+            params.push((format!("Filter.1.Value.{}", index + 1), state.to_string()));
+        }
+        params.push(("MaxResults".to_string(), PAGE_SIZE.to_string()));
+
+        loop {
+            let resp: DescribeInstancesResponse = self
+                .call(Service::Ec2, "DescribeInstances", &params)
+                .await?;
+            page(
+                resp.reservation_set
+                    .items
+                    .into_iter()
+                    .flat_map(|reservation| reservation.instances_set.items)
+                    .map(Server::from)
+                    .collect(),
+            );
+            match resp.next_token {
+                Some(token) => Self::set_token(&mut params, token),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn create_server(&self, request: CreateServer) -> Result<(Server, Vec<String>)> {
+        if request.image.trim().is_empty() {
+            return Err(CartonError::Validation(
+                "An AMI id is required to create an EC2 instance".to_string(),
+            )
+            .into());
+        }
+
+        let mut params = vec![
+            ("ImageId".to_string(), request.image),
+            ("InstanceType".to_string(), request.server_type),
+            ("MinCount".to_string(), "1".to_string()),
+            ("MaxCount".to_string(), "1".to_string()),
+            (
+                "TagSpecification.1.ResourceType".to_string(),
+                "instance".to_string(),
+            ),
+            (
+                "TagSpecification.1.Tag.1.Key".to_string(),
+                "Name".to_string(),
+            ),
+            ("TagSpecification.1.Tag.1.Value".to_string(), request.name),
+        ];
+        for (index, (key, value)) in request.labels.into_iter().enumerate() {
+            params.push((format!("TagSpecification.1.Tag.{}.Key", index + 2), key));
+            params.push((format!("TagSpecification.1.Tag.{}.Value", index + 2), value));
+        }
+        // EC2 only accepts a single key pair per instance
+        if let Some(key) = request.ssh_keys.into_iter().next() {
+            params.push(("KeyName".to_string(), key));
+        }
+        if let Some(zone) = request.location {
+            params.push(("Placement.AvailabilityZone".to_string(), zone));
+        }
+        if let Some(user_data) = request.user_data {
+            params.push(("UserData".to_string(), BASE64_STANDARD.encode(user_data)));
+        }
+
+        let resp: RunInstancesResponse = self.call(Service::Ec2, "RunInstances", &params).await?;
+        let server = resp
+            .instances_set
+            .items
+            .into_iter()
+            .next()
+            .map(Server::from)
+            .ok_or(anyhow!("RunInstances returned no instance"))?;
+        Ok((server, Vec::new()))
+    }
+
+    async fn delete_server(&self, id: &str) -> Result<Option<String>> {
+        self.call::<serde::de::IgnoredAny>(
+            Service::Ec2,
+            "TerminateInstances",
+            &Self::instance_params(id),
+        )
+        .await?;
+        Ok(None)
+    }
+
+    async fn power(&self, id: &str, action: PowerAction) -> Result<Option<String>> {
+        let mut params = Self::instance_params(id);
+        let action = match action {
+            PowerAction::PowerOn => "StartInstances",
+            PowerAction::Shutdown => "StopInstances",
+            PowerAction::PowerOff => {
+                params.push(("Force".to_string(), "true".to_string()));
+                "StopInstances"
+            }
+            PowerAction::Reboot => "RebootInstances",
+            PowerAction::Reset => {
+                return Err(CartonError::Validation(
+                    "EC2 does not support hard resets, use reboot instead".to_string(),
+                )
+                .into())
+            }
+        };
+        self.call::<serde::de::IgnoredAny>(Service::Ec2, action, &params)
+            .await?;
+        Ok(None)
+    }
+
+    async fn list_server_types(&self) -> Result<Vec<ServerType>> {
+        let mut params = Vec::new();
+        let mut types = Vec::new();
+        loop {
+            let resp: DescribeInstanceTypesResponse = self
+                .call(Service::Ec2, "DescribeInstanceTypes", &params)
+                .await?;
+            types.extend(resp.instance_type_set.items);
+            match resp.next_token {
+                Some(token) => Self::set_token(&mut params, token),
+                None => break,
+            }
+        }
+        Ok(types
+            .into_iter()
+            .map(|info| ServerType {
+                description: info.instance_type.clone(),
+                name: info.instance_type,
+                cores: info.v_cpu_info.default_v_cpus,
+                memory: info.memory_info.size_in_mi_b / 1024.0,
+                disk: info
+                    .instance_storage_info
+                    .map(|storage| storage.total_size_in_g_b)
+                    .unwrap_or(0.0),
+                price_monthly: None,
+                locations: Vec::new(),
+            })
+            .collect())
+    }
+
+    async fn list_images(&self) -> Result<Vec<Image>> {
+        let params = [("Owner.1".to_string(), "self".to_string())];
+        let resp: DescribeImagesResponse =
+            self.call(Service::Ec2, "DescribeImages", &params).await?;
+        Ok(resp
+            .images_set
+            .items
+            .into_iter()
+            .map(|image| Image {
+                description: image
+                    .description
+                    .or(image.name)
+                    .unwrap_or(image.image_id.clone()),
+                name: image.image_id,
+            })
+            .collect())
+    }
+
+    async fn list_locations(&self) -> Result<Vec<Location>> {
+        let resp: DescribeAvailabilityZonesResponse = self
+            .call(Service::Ec2, "DescribeAvailabilityZones", &[])
+            .await?;
+        Ok(resp
+            .availability_zone_info
+            .items
+            .into_iter()
+            .map(|zone| Location {
+                name: zone.zone_name,
+                description: zone.region_name,
+            })
+            .collect())
+    }
+
+    async fn list_ssh_keys(&self) -> Result<Vec<String>> {
+        let resp: DescribeKeyPairsResponse =
+            self.call(Service::Ec2, "DescribeKeyPairs", &[]).await?;
+        Ok(resp
+            .key_set
+            .items
+            .into_iter()
+            .map(|key| key.key_name)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::testing::serve;
+
+    fn amazon(endpoint: String) -> Amazon {
+        Amazon::new(&Auth::new(
+            AuthPlatform::Amazon,
+            "key:secret".to_string(),
+            Some("eu-west-1".to_string()),
+            Some(endpoint),
+        ))
+        .unwrap()
+    }
+
+    fn instances(ids: &[&str], next_token: Option<&str>) -> String {
+        let instances = ids
+            .iter()
+            .map(|id| {
+                format!(
+                    "<item><instanceId>{}</instanceId><instanceState><name>running</name>\
+                     </instanceState><instanceType>t3.micro</instanceType><placement>\
+                     <availabilityZone>eu-west-1a</availabilityZone></placement></item>",
+                    id
+                )
+            })
+            .collect::<String>();
+        format!(
+            "<DescribeInstancesResponse><reservationSet><item><instancesSet>{}</instancesSet>\
+             </item></reservationSet>{}</DescribeInstancesResponse>",
+            instances,
+            next_token.map_or(String::new(), |token| format!(
+                "<nextToken>{}</nextToken>",
+                token
+            ))
+        )
+    }
+
+    #[test]
+    fn instance_converts_to_server() {
+        let resp: DescribeInstancesResponse = quick_xml::de::from_str(
+            "<DescribeInstancesResponse><reservationSet><item><instancesSet><item>
+                <instanceId>i-0abc</instanceId>
+                <imageId>ami-123</imageId>
+                <instanceState><code>80</code><name>stopped</name></instanceState>
+                <instanceType>t3.small</instanceType>
+                <launchTime>2024-01-01T00:00:00.000Z</launchTime>
+                <placement><availabilityZone>eu-west-1b</availabilityZone></placement>
+                <ipAddress>203.0.113.7</ipAddress>
+                <tagSet>
+                    <item><key>Name</key><value>web-1</value></item>
+                    <item><key>role</key><value>web</value></item>
+                </tagSet>
+            </item></instancesSet></item></reservationSet></DescribeInstancesResponse>",
+        )
+        .unwrap();
+        let instance = resp.reservation_set.items.into_iter().next().unwrap();
+        let server = Server::from(instance.instances_set.items.into_iter().next().unwrap());
+        assert_eq!(server.id, "i-0abc");
+        assert_eq!(server.name, "web-1");
+        assert_eq!(server.state, ServerState::Off);
+        assert_eq!(server.server_type, "t3.small");
+        assert_eq!(server.location, "eu-west-1b");
+        assert_eq!(server.image.as_deref(), Some("ami-123"));
+        assert_eq!(server.ipv4.as_deref(), Some("203.0.113.7"));
+        assert_eq!(server.ipv6, None);
+        // The name tag becomes the name, not a label
+        assert_eq!(
+            server.labels,
+            BTreeMap::from([("role".to_string(), "web".to_string())])
+        );
+    }
+
+    #[test]
+    fn unnamed_instance_goes_by_its_id() {
+        let resp: RunInstancesResponse = quick_xml::de::from_str(
+            "<RunInstancesResponse><instancesSet><item>
+                <instanceId>i-1</instanceId>
+                <instanceState><code>0</code><name>pending</name></instanceState>
+                <instanceType>t3.micro</instanceType>
+                <placement><availabilityZone>eu-west-1a</availabilityZone></placement>
+            </item></instancesSet></RunInstancesResponse>",
+        )
+        .unwrap();
+        let server = Server::from(resp.instances_set.items.into_iter().next().unwrap());
+        assert_eq!(server.name, "i-1");
+        assert_eq!(server.state, ServerState::Starting);
+        assert!(server.labels.is_empty());
+    }
+
+    #[test]
+    fn unsupported_requests_are_invalid() {
+        let amazon = amazon("http://127.0.0.1:1".to_string());
+        let rt = Runtime::new().unwrap();
+        let reset = rt
+            .block_on(amazon.power("i-1", PowerAction::Reset))
+            .unwrap_err();
+        assert_eq!(CartonError::classify(&reset).title(), "Invalid input");
+        let request = CreateServer {
+            name: "web-1".to_string(),
+            server_type: "t3.micro".to_string(),
+            ..Default::default()
+        };
+        let create = rt.block_on(amazon.create_server(request)).unwrap_err();
+        assert_eq!(CartonError::classify(&create).title(), "Invalid input");
+    }
+
+    #[test]
+    fn lists_every_instance_page() {
+        let (endpoint, requests) = serve(vec![
+            instances(&["i-1", "i-2"], Some("page-2")),
+            instances(&["i-3"], None),
+        ]);
+        let servers = Runtime::new()
+            .unwrap()
+            .block_on(amazon(endpoint).list_servers())
+            .unwrap();
+        assert_eq!(
+            servers
+                .iter()
+                .map(|server| server.id.as_str())
+                .collect::<Vec<_>>(),
+            ["i-1", "i-2", "i-3"]
+        );
+
+        let requests = requests.join().unwrap();
+        assert!(requests[0].contains("MaxResults=100"));
+        assert!(!requests[0].contains("NextToken"));
+        assert!(requests[1].contains("MaxResults=100"));
+        assert!(requests[1].contains("NextToken=page-2"));
+    }
+
+    #[test]
+    fn status_counts_every_page() {
+        let groups = |count: usize, next_token: Option<&str>| {
+            format!(
+                "<DescribeSecurityGroupsResponse><securityGroupInfo>{}</securityGroupInfo>{}\
+                 </DescribeSecurityGroupsResponse>",
+                "<item><groupId>sg-1</groupId></item>".repeat(count),
+                next_token.map_or(String::new(), |token| format!(
+                    "<nextToken>{}</nextToken>",
+                    token
+                ))
+            )
+        };
+        let balancers = |count: usize, marker: Option<&str>| {
+            format!(
+                "<DescribeLoadBalancersResponse><DescribeLoadBalancersResult><LoadBalancers>{}\
+                 </LoadBalancers>{}</DescribeLoadBalancersResult></DescribeLoadBalancersResponse>",
+                "<member><LoadBalancerName>lb</LoadBalancerName></member>".repeat(count),
+                marker.map_or(String::new(), |marker| format!(
+                    "<NextMarker>{}</NextMarker>",
+                    marker
+                ))
+            )
+        };
+        let (endpoint, requests) = serve(vec![
+            instances(&["i-1"], None),
+            "<DescribeAddressesResponse><addressesSet><item><publicIp>203.0.113.1</publicIp>\
+             </item></addressesSet></DescribeAddressesResponse>"
+                .to_string(),
+            groups(2, Some("groups-2")),
+            groups(1, None),
+            balancers(3, Some("lbs-2")),
+            balancers(2, None),
+        ]);
+        let status = Runtime::new()
+            .unwrap()
+            .block_on(amazon(endpoint).status())
+            .unwrap();
+        assert_eq!(status.servers, 1);
+        assert_eq!(status.primary_ips, 1);
+        assert_eq!(status.firewalls, 3);
+        assert_eq!(status.load_balancers, 5);
+
+        let requests = requests.join().unwrap();
+        assert!(requests[2].contains("Action=DescribeSecurityGroups"));
+        assert!(requests[2].contains("MaxResults=100"));
+        assert!(requests[3].contains("NextToken=groups-2"));
+        assert!(requests[4].contains("Action=DescribeLoadBalancers"));
+        assert!(!requests[4].contains("Marker"));
+        assert!(requests[5].contains("Marker=lbs-2"));
+    }
+}
@@ -0,0 +1,888 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::constants::{Auth, AuthPlatform, ProviderStatus};
+use crate::error::CartonError;
+use crate::providers::http::Http;
+use crate::providers::{
+    CloudProvider, CreateServer, Image, Location, PowerAction, Server, ServerState, ServerType,
+};
+
+const BASE_URL: &str = "https://compute.googleapis.com/compute/v1";
+const SCOPE: &str = "https://www.googleapis.com/auth/compute";
+const ZONE: &str = "europe-west1-b";
+const IMAGE: &str = "projects/debian-cloud/global/images/family/debian-12";
+/// Longest wait on an operation, each wait call returns after about two minutes
+const OPERATION_WAIT: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Deserialize)]
+struct ServiceAccountKey {
+    project_id: String,
+    private_key: String,
+    client_email: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    code: u16,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct OperationErrorDetail {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OperationError {
+    #[serde(default)]
+    errors: Vec<OperationErrorDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Operation {
+    name: String,
+    status: String,
+    #[serde(default)]
+    error: Option<OperationError>,
+    #[serde(default, rename = "httpErrorStatusCode")]
+    http_error_status_code: Option<u16>,
+}
+
+impl OperationErrorDetail {
+    /// Sorts the error of a failed operation by its code, or else by the HTTP
+    /// status the operation failed with.
+    fn classify(self, status: Option<u16>) -> CartonError {
+        let message = format!("{} ({})", self.message, self.code);
+        match self.code.as_str() {
+            code if code.ends_with("NOT_FOUND") => CartonError::NotFound(message),
+            "PERMISSION_DENIED" | "PERMISSIONS_ERROR" => CartonError::Auth(message),
+            "RATE_LIMIT_EXCEEDED" => CartonError::RateLimited(message),
+            code if code.starts_with("INVALID_") => CartonError::Validation(message),
+            // Comes with a 403, yet the credentials are fine
+            "QUOTA_EXCEEDED" => CartonError::Provider(message),
+            _ => match status.and_then(|status| StatusCode::from_u16(status).ok()) {
+                Some(status) => CartonError::from_status(status, message),
+                None => CartonError::Provider(message),
+            },
+        }
+    }
+}
+
+/// A list response that may continue on another page.
+trait Paged: DeserializeOwned + Send {
+    fn next_page_token(&self) -> Option<String>;
+}
+
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    #[serde(default = "Vec::new")]
+    items: Vec<T>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+impl<T: DeserializeOwned + Send> Paged for List<T> {
+    fn next_page_token(&self) -> Option<String> {
+        self.next_page_token.clone()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessConfig {
+    #[serde(default)]
+    nat_i_p: Option<String>,
+    #[serde(default)]
+    external_ipv6: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NetworkInterface {
+    #[serde(default)]
+    access_configs: Vec<AccessConfig>,
+    #[serde(default)]
+    ipv6_access_configs: Vec<AccessConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AttachedDisk {
+    #[serde(default)]
+    boot: bool,
+    #[serde(default)]
+    disk_size_gb: Option<String>,
+    #[serde(default)]
+    licenses: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Instance {
+    name: String,
+    status: String,
+    zone: String,
+    machine_type: String,
+    #[serde(default)]
+    creation_timestamp: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    network_interfaces: Vec<NetworkInterface>,
+    #[serde(default)]
+    disks: Vec<AttachedDisk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstancesScopedList {
+    #[serde(default)]
+    instances: Vec<Instance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AggregatedList<T> {
+    #[serde(default = "HashMap::new")]
+    items: HashMap<String, T>,
+    #[serde(rename = "nextPageToken")]
+    next_page_token: Option<String>,
+}
+
+impl<T: DeserializeOwned + Send> Paged for AggregatedList<T> {
+    fn next_page_token(&self) -> Option<String> {
+        self.next_page_token.clone()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AddressesScopedList {
+    #[serde(default)]
+    addresses: Vec<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ForwardingRulesScopedList {
+    #[serde(default)]
+    forwarding_rules: Vec<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MachineType {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    zone: String,
+    guest_cpus: i32,
+    memory_mb: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MachineTypesScopedList {
+    #[serde(default)]
+    machine_types: Vec<MachineType>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedResource {
+    name: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataItem {
+    key: String,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    items: Vec<MetadataItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Project {
+    common_instance_metadata: Option<Metadata>,
+}
+
+/// Returns the last segment of a resource URL, e.g. the zone name of a zone link.
+fn basename(url: &str) -> String {
+    url.rsplit('/').next().unwrap_or(url).to_string()
+}
+
+impl From<Instance> for Server {
+    fn from(instance: Instance) -> Self {
+        let zone = basename(&instance.zone);
+        let boot = instance.disks.iter().find(|disk| disk.boot);
+
+        Self {
+            id: format!("{}/{}", zone, instance.name),
+            name: instance.name,
+            account: String::new(),
+            provider: AuthPlatform::Google,
+            state: match instance.status.as_str() {
+                "PROVISIONING" | "STAGING" | "REPAIRING" => ServerState::Provisioning,
+                "RUNNING" => ServerState::Running,
+                "STOPPING" | "SUSPENDING" => ServerState::Stopping,
+                "STOPPED" | "SUSPENDED" | "TERMINATED" => ServerState::Off,
+                _ => ServerState::Unknown,
+            },
+            ipv4: instance
+                .network_interfaces
+                .iter()
+                .flat_map(|nic| nic.access_configs.iter())
+                .find_map(|config| config.nat_i_p.clone()),
+            ipv6: instance
+                .network_interfaces
+                .iter()
+                .flat_map(|nic| nic.ipv6_access_configs.iter())
+                .find_map(|config| config.external_ipv6.clone()),
+            created: instance.creation_timestamp,
+            location: zone,
+            image: boot.and_then(|disk| disk.licenses.last().map(|l| basename(l))),
+            labels: instance.labels,
+            server_type: basename(&instance.machine_type),
+            disk_size: boot
+                .and_then(|disk| disk.disk_size_gb.as_ref())
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            traffic: (0, 0),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Google {
+    http: Http,
+    key: ServiceAccountKey,
+    base_url: String,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl Google {
+    /// Builds the backend from the path of a service-account key file.
+    pub fn new(auth: &Auth) -> Result<Self> {
+        let key = std::fs::read_to_string(&auth.token)
+            .with_context(|| format!("Cannot read service-account key {}", auth.token))?;
+        let key: ServiceAccountKey =
+            serde_json::from_str(&key).context("Invalid service-account key file")?;
+
+        Ok(Self {
+            http: Http::default(),
+            key,
+            base_url: auth
+                .endpoint
+                .as_deref()
+                .unwrap_or(BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            token: Mutex::new(None),
+        })
+    }
+
+    async fn access_token(&self) -> Result<String> {
+        if let Some((token, expiry)) = self.token.lock().unwrap().as_ref() {
+            if Instant::now() < *expiry {
+                return Ok(token.clone());
+            }
+        }
+
+        let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let claims = Claims {
+            iss: &self.key.client_email,
+            scope: SCOPE,
+            aud: &self.key.token_uri,
+            iat,
+            exp: iat + 3600,
+        };
+        let assertion = jsonwebtoken::encode(
+            &Header::new(Algorithm::RS256),
+            &claims,
+            &EncodingKey::from_rsa_pem(self.key.private_key.as_bytes())?,
+        )?;
+
+        let request = self.http.client().post(&self.key.token_uri).form(&[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+            ("assertion", assertion.as_str()),
+        ]);
+        // Exchanging the same assertion twice just hands out another token
+        let resp = self.http.send(request, true).await?;
+        if !resp.status().is_success() {
+            let message = format!("Token exchange failed with status {}", resp.status());
+            return Err(match resp.status() {
+                // A rejected assertion means a bad or revoked key
+                StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED => CartonError::Auth(message),
+                status => CartonError::from_status(status, message),
+            }
+            .into());
+        }
+        let resp: TokenResponse = resp.json().await?;
+
+        // Refresh a minute early so in-flight requests never carry a stale token
+        let expiry = Instant::now() + Duration::from_secs(resp.expires_in.saturating_sub(60));
+        *self.token.lock().unwrap() = Some((resp.access_token.clone(), expiry));
+        Ok(resp.access_token)
+    }
+
+    fn url(&self, path: &str) -> String {
+        let project = format!("{}/projects/{}", self.base_url, self.key.project_id);
+        if path.is_empty() {
+            project
+        } else {
+            format!("{}/{}", project, path)
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let idempotent = method.is_idempotent();
+        let mut request = self
+            .http
+            .client()
+            .request(method, self.url(path))
+            .bearer_auth(self.access_token().await?);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let resp = self.http.send(request, idempotent).await?;
+        let status = resp.status();
+        let content = resp.text().await?;
+
+        if status.is_success() {
+            serde_json::from_str(&content)
+                .with_context(|| format!("Unexpected response for {}", path))
+        } else {
+            let message = match serde_json::from_str::<ErrorResponse>(&content) {
+                Ok(resp) => format!("{} ({})", resp.error.message, resp.error.code),
+                Err(_) => format!("Request to {} failed with status {}", path, status),
+            };
+            Err(CartonError::from_status(status, message).into())
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(reqwest::Method::GET, path, None).await
+    }
+
+    /// Follows `nextPageToken` through every page of a list call.
+    async fn pages<P: Paged>(&self, path: &str, mut each: impl FnMut(P) + Send) -> Result<()> {
+        let mut url = path.to_string();
+        loop {
+            let page: P = self.get(&url).await?;
+            let token = page.next_page_token();
+            each(page);
+            match token {
+                Some(token) => {
+                    url = format!(
+                        "{}?{}",
+                        path,
+                        serde_urlencoded::to_string([("pageToken", token)])?
+                    )
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn list<T: DeserializeOwned + Send>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        self.pages(path, |page: List<T>| items.extend(page.items))
+            .await?;
+        Ok(items)
+    }
+
+    /// The `user:key` lines of the project-wide SSH keys.
+    async fn ssh_key_lines(&self) -> Result<Vec<String>> {
+        let project: Project = self.get("").await?;
+        Ok(project
+            .common_instance_metadata
+            .map(|metadata| metadata.items)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|item| item.key == "ssh-keys")
+            .flat_map(|item| {
+                item.value
+                    .lines()
+                    .filter(|line| line.contains(':'))
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    /// Splits a `zone/name` server id into its parts.
+    fn parse_id(id: &str) -> Result<(&str, &str)> {
+        id.split_once('/')
+            .ok_or(anyhow!("Invalid instance id {}, expected zone/name", id))
+    }
+}
+
+#[async_trait]
+impl CloudProvider for Google {
+    fn platform(&self) -> AuthPlatform {
+        AuthPlatform::Google
+    }
+
+    async fn status(&self) -> Result<ProviderStatus> {
+        let mut overview = ProviderStatus::new(self.platform());
+
+        overview.servers = self.list_servers().await?.len();
+        self.pages(
+            "aggregated/addresses",
+            |page: AggregatedList<AddressesScopedList>| {
+                overview.primary_ips += page
+                    .items
+                    .values()
+                    .map(|scope| scope.addresses.len())
+                    .sum::<usize>()
+            },
+        )
+        .await?;
+        overview.firewalls = self
+            .list::<serde::de::IgnoredAny>("global/firewalls")
+            .await?
+            .len();
+        self.pages(
+            "aggregated/forwardingRules",
+            |page: AggregatedList<ForwardingRulesScopedList>| {
+                overview.load_balancers += page
+                    .items
+                    .values()
+                    .map(|scope| scope.forwarding_rules.len())
+                    .sum::<usize>()
+            },
+        )
+        .await?;
+
+        overview.quota = self.http.quota();
+        Ok(overview)
+    }
+
+    async fn list_server_pages(&self, page: &(dyn Fn(Vec<Server>) + Send + Sync)) -> Result<()> {
+        self.pages(
+            "aggregated/instances",
+            |resp: AggregatedList<InstancesScopedList>| {
+                let mut servers = resp
+                    .items
+                    .into_values()
+                    .flat_map(|scope| scope.instances)
+                    .map(Server::from)
+                    .collect::<Vec<_>>();
+                // Zones come back as an unordered map, keep the table stable between refreshes
+                servers.sort_by(|a, b| a.id.cmp(&b.id));
+                page(servers)
+            },
+        )
+        .await
+    }
+
+    async fn create_server(&self, request: CreateServer) -> Result<(Server, Vec<String>)> {
+        let zone = request.location.unwrap_or(ZONE.to_string());
+        let image = match request.image.as_str() {
+            "" => IMAGE.to_string(),
+            // Bare names are the project's own images, as offered by list_images
+            name if !name.contains('/') => {
+                format!("projects/{}/global/images/{}", self.key.project_id, name)
+            }
+            path => path.to_string(),
+        };
+
+        // Project-wide SSH keys apply to new instances anyway, the picked ones
+        // are set on the instance as well so they survive project changes
+        let mut metadata = Vec::new();
+        if !request.ssh_keys.is_empty() {
+            let known = self.ssh_key_lines().await?;
+            let mut keys = Vec::new();
+            for key in &request.ssh_keys {
+                let lines = known
+                    .iter()
+                    .filter(|line| line.split_once(':').map(|(user, _)| user) == Some(key))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                match lines.is_empty() {
+                    // A full `user:key` line is taken as is
+                    true if key.contains(':') => keys.push(key.clone()),
+                    true => bail!("Unknown SSH key {}", key),
+                    false => keys.extend(lines),
+                }
+            }
+            metadata.push(json!({ "key": "ssh-keys", "value": keys.join("\n") }));
+        }
+        if let Some(user_data) = request.user_data {
+            metadata.push(json!({ "key": "user-data", "value": user_data }));
+        }
+
+        let mut body = json!({
+            "name": request.name,
+            "machineType": format!("zones/{}/machineTypes/{}", zone, request.server_type),
+            "disks": [{
+                "boot": true,
+                "autoDelete": true,
+                "initializeParams": { "sourceImage": image },
+            }],
+            "networkInterfaces": [{
+                "network": "global/networks/default",
+                "accessConfigs": [{ "type": "ONE_TO_ONE_NAT", "name": "External NAT" }],
+            }],
+        });
+        if !request.labels.is_empty() {
+            body["labels"] = json!(request.labels);
+        }
+        if !metadata.is_empty() {
+            body["metadata"] = json!({ "items": metadata });
+        }
+        let operation = self
+            .send::<Operation>(
+                reqwest::Method::POST,
+                &format!("zones/{}/instances", zone),
+                Some(body),
+            )
+            .await?;
+        let action = format!("{}/{}", zone, operation.name);
+
+        let server = Server {
+            id: format!("{}/{}", zone, request.name),
+            name: request.name,
+            provider: self.platform(),
+            state: ServerState::Provisioning,
+            location: zone,
+            image: Some(basename(&image)),
+            server_type: request.server_type,
+            ..Default::default()
+        };
+        Ok((server, vec![action]))
+    }
+
+    async fn delete_server(&self, id: &str) -> Result<Option<String>> {
+        let (zone, name) = Self::parse_id(id)?;
+        let operation = self
+            .send::<Operation>(
+                reqwest::Method::DELETE,
+                &format!("zones/{}/instances/{}", zone, name),
+                None,
+            )
+            .await?;
+        Ok(Some(format!("{}/{}", zone, operation.name)))
+    }
+
+    async fn wait_action(&self, id: &str) -> Result<()> {
+        let (zone, name) = Self::parse_id(id)?;
+        let deadline = Instant::now() + OPERATION_WAIT;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(CartonError::Network(format!(
+                    "Operation {} still running after {}s",
+                    name,
+                    OPERATION_WAIT.as_secs()
+                ))
+                .into());
+            }
+            // The wait call returns once the operation is done or after about two minutes
+            let operation = self
+                .send::<Operation>(
+                    reqwest::Method::POST,
+                    &format!("zones/{}/operations/{}/wait", zone, name),
+                    None,
+                )
+                .await?;
+            if operation.status == "DONE" {
+                return match operation
+                    .error
+                    .and_then(|err| err.errors.into_iter().next())
+                {
+                    Some(err) => Err(err.classify(operation.http_error_status_code).into()),
+                    None => Ok(()),
+                };
+            }
+        }
+    }
+
+    async fn power(&self, id: &str, action: PowerAction) -> Result<Option<String>> {
+        let (zone, name) = Self::parse_id(id)?;
+        let verb = match action {
+            PowerAction::PowerOn => "start",
+            PowerAction::Shutdown | PowerAction::PowerOff => "stop",
+            PowerAction::Reset => "reset",
+            PowerAction::Reboot => {
+                return Err(CartonError::Validation(
+                    "Compute Engine has no soft reboot, use reset instead".to_string(),
+                )
+                .into())
+            }
+        };
+        let operation = self
+            .send::<Operation>(
+                reqwest::Method::POST,
+                &format!("zones/{}/instances/{}/{}", zone, name, verb),
+                None,
+            )
+            .await?;
+        Ok(Some(format!("{}/{}", zone, operation.name)))
+    }
+
+    async fn list_server_types(&self) -> Result<Vec<ServerType>> {
+        // Zones offer different machine types, each type keeps the zones it is in
+        let mut types = BTreeMap::new();
+        self.pages(
+            "aggregated/machineTypes",
+            |page: AggregatedList<MachineTypesScopedList>| {
+                for machine in page
+                    .items
+                    .into_values()
+                    .flat_map(|scope| scope.machine_types)
+                {
+                    let zone = basename(&machine.zone);
+                    types
+                        .entry(machine.name.clone())
+                        .or_insert_with(|| ServerType {
+                            name: machine.name,
+                            description: machine.description,
+                            cores: machine.guest_cpus,
+                            memory: machine.memory_mb / 1024.0,
+                            disk: 0.0,
+                            price_monthly: None,
+                            locations: Vec::new(),
+                        })
+                        .locations
+                        .push(zone);
+                }
+            },
+        )
+        .await?;
+        Ok(types
+            .into_values()
+            .map(|mut server_type: ServerType| {
+                server_type.locations.sort();
+                server_type
+            })
+            .collect())
+    }
+
+    async fn list_images(&self) -> Result<Vec<Image>> {
+        Ok(self
+            .list::<NamedResource>("global/images")
+            .await?
+            .into_iter()
+            .map(|image| Image {
+                name: image.name,
+                description: image.description,
+            })
+            .collect())
+    }
+
+    async fn list_locations(&self) -> Result<Vec<Location>> {
+        Ok(self
+            .list::<NamedResource>("zones")
+            .await?
+            .into_iter()
+            .map(|zone| Location {
+                name: zone.name,
+                description: zone.description,
+            })
+            .collect())
+    }
+
+    async fn list_ssh_keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .ssh_key_lines()
+            .await?
+            .iter()
+            .filter_map(|line| line.split_once(':').map(|(user, _)| user.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::testing::serve;
+
+    /// A client for the `demo` project that already holds an access token.
+    fn google(endpoint: String) -> Google {
+        Google {
+            http: Http::default(),
+            key: ServiceAccountKey {
+                project_id: "demo".to_string(),
+                private_key: String::new(),
+                client_email: String::new(),
+                token_uri: String::new(),
+            },
+            base_url: endpoint,
+            token: Mutex::new(Some((
+                "token".to_string(),
+                Instant::now() + Duration::from_secs(3600),
+            ))),
+        }
+    }
+
+    fn instance(zone: &str, name: &str) -> serde_json::Value {
+        json!({
+            "name": name,
+            "status": "RUNNING",
+            "zone": format!("https://www.googleapis.com/compute/v1/projects/demo/zones/{}", zone),
+            "machineType": format!("zones/{}/machineTypes/e2-small", zone)
+        })
+    }
+
+    #[test]
+    fn instance_converts_to_server() {
+        let instance: Instance = serde_json::from_value(json!({
+            "name": "web-1",
+            "status": "TERMINATED",
+            "zone": "https://www.googleapis.com/compute/v1/projects/demo/zones/europe-west1-b",
+            "machineType": "https://www.googleapis.com/compute/v1/projects/demo/zones/europe-west1-b/machineTypes/e2-medium",
+            "creationTimestamp": "2024-01-01T00:00:00.000-07:00",
+            "labels": {"role": "web"},
+            "networkInterfaces": [{
+                "accessConfigs": [{"natIP": "203.0.113.9"}],
+                "ipv6AccessConfigs": [{"externalIpv6": "2001:db8::1"}]
+            }],
+            "disks": [
+                {"boot": false, "diskSizeGb": "100"},
+                {
+                    "boot": true,
+                    "diskSizeGb": "20",
+                    "licenses": ["https://www.googleapis.com/compute/v1/projects/debian-cloud/global/licenses/debian-12-bookworm"]
+                }
+            ]
+        }))
+        .unwrap();
+        let server = Server::from(instance);
+        assert_eq!(server.id, "europe-west1-b/web-1");
+        assert_eq!(server.name, "web-1");
+        assert_eq!(server.state, ServerState::Off);
+        assert_eq!(server.location, "europe-west1-b");
+        assert_eq!(server.server_type, "e2-medium");
+        assert_eq!(server.ipv4.as_deref(), Some("203.0.113.9"));
+        assert_eq!(server.ipv6.as_deref(), Some("2001:db8::1"));
+        // Only the boot disk counts
+        assert_eq!(server.image.as_deref(), Some("debian-12-bookworm"));
+        assert_eq!(server.disk_size, 20);
+        assert_eq!(server.labels["role"], "web");
+    }
+
+    #[test]
+    fn operation_errors_are_typed() {
+        let error = |code: &str, status| {
+            OperationErrorDetail {
+                code: code.to_string(),
+                message: "failed".to_string(),
+            }
+            .classify(status)
+        };
+        assert_eq!(
+            error("RESOURCE_NOT_FOUND", Some(404)),
+            CartonError::NotFound("failed (RESOURCE_NOT_FOUND)".to_string())
+        );
+        assert_eq!(
+            error("PERMISSION_DENIED", None).title(),
+            "Authentication failed"
+        );
+        assert_eq!(error("RATE_LIMIT_EXCEEDED", None).title(), "Rate limited");
+        assert_eq!(error("INVALID_FIELD_VALUE", None).title(), "Invalid input");
+        assert_eq!(error("QUOTA_EXCEEDED", Some(403)).title(), "Provider error");
+        assert_eq!(
+            error("ZONE_RESOURCE_POOL_EXHAUSTED", Some(503)).title(),
+            "Provider error"
+        );
+        assert_eq!(error("UNKNOWN", Some(400)).title(), "Invalid input");
+        assert_eq!(error("UNKNOWN", None).title(), "Provider error");
+    }
+
+    #[test]
+    fn soft_reboot_is_invalid() {
+        let err = Runtime::new()
+            .unwrap()
+            .block_on(
+                google("http://127.0.0.1:1".to_string())
+                    .power("europe-west1-b/web-1", PowerAction::Reboot),
+            )
+            .unwrap_err();
+        assert_eq!(CartonError::classify(&err).title(), "Invalid input");
+    }
+
+    #[test]
+    fn lists_every_page_in_order() {
+        let (endpoint, requests) = serve(vec![
+            json!({
+                "items": {
+                    "zones/us-east1-b": {"instances": [instance("us-east1-b", "api-1")]},
+                    "zones/europe-west1-b": {"instances": [instance("europe-west1-b", "web-1")]},
+                    "zones/asia-east1-a": {"warning": {"code": "NO_RESULTS_ON_PAGE"}}
+                },
+                "nextPageToken": "page 2"
+            })
+            .to_string(),
+            json!({
+                "items": {"zones/europe-west1-b": {"instances": [instance("europe-west1-b", "web-2")]}}
+            })
+            .to_string(),
+        ]);
+        let servers = Runtime::new()
+            .unwrap()
+            .block_on(google(endpoint).list_servers())
+            .unwrap();
+        assert_eq!(
+            servers
+                .iter()
+                .map(|server| server.id.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "europe-west1-b/web-1",
+                "us-east1-b/api-1",
+                "europe-west1-b/web-2"
+            ]
+        );
+
+        let requests = requests.join().unwrap();
+        assert!(requests[0].starts_with("GET /projects/demo/aggregated/instances "));
+        assert!(
+            requests[1].starts_with("GET /projects/demo/aggregated/instances?pageToken=page+2 ")
+        );
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use hcloud::models::{
+    self, action, server::Status, CreateServerRequest, CreateServerRequestFirewalls,
+    CreateServerRequestPublicNet, IpType, ReplaceServerRequest,
+};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::constants::{Auth, AuthPlatform, ProviderStatus};
+use crate::error::CartonError;
+use crate::providers::http::Http;
+use crate::providers::{
+    Action, ActionStatus, CloudProvider, CreateServer, Image, Location, PowerAction, Resource,
+    Server, ServerState, ServerType,
+};
+
+const BASE_URL: &str = "https://api.hetzner.cloud/v1";
+const IMAGE: &str = "fedora-41";
+const LOCATION: &str = "fsn1";
+/// The largest page the API hands out
+const PER_PAGE: i64 = 50;
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+/// One page of a list endpoint, the items sit under the resource name.
+#[derive(Debug, Deserialize)]
+struct Page {
+    meta: Option<models::Meta>,
+    #[serde(flatten)]
+    items: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Talks to the Hetzner Cloud API directly, using the hcloud crate only for its
+/// models, as its generated client hides the rate limit headers.
+#[derive(Debug)]
+pub struct Hetzner {
+    http: Http,
+    token: String,
+    base_url: String,
+}
+
+impl Hetzner {
+    pub fn new(auth: &Auth) -> Self {
+        Self {
+            http: Http::default(),
+            token: auth.token.to_string(),
+            base_url: auth
+                .endpoint
+                .as_deref()
+                .unwrap_or(BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+
+    fn parse_id(id: &str) -> Result<i64> {
+        Ok(id.parse::<i64>()?)
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T> {
+        let idempotent = method.is_idempotent();
+        let mut request = self
+            .http
+            .client()
+            .request(method, format!("{}/{}", self.base_url, path))
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let resp = self.http.send(request, idempotent).await?;
+        let status = resp.status();
+        let content = resp.text().await?;
+
+        if status.is_success() {
+            serde_json::from_str(&content)
+                .with_context(|| format!("Unexpected response for {}", path))
+        } else {
+            let message = match serde_json::from_str::<ErrorResponse>(&content) {
+                Ok(resp) => format!("{} ({})", resp.error.message, resp.error.code),
+                Err(_) => format!("Request to {} failed with status {}", path, status),
+            };
+            Err(CartonError::from_status(status, message).into())
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send(Method::GET, path, None).await
+    }
+
+    /// Walks every page of a list endpoint, handing over the items of each.
+    async fn pages<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        mut each: impl FnMut(Vec<T>) + Send,
+    ) -> Result<()> {
+        let mut page = 1;
+        loop {
+            let mut resp: Page = self
+                .get(&format!("{}?page={}&per_page={}", path, page, PER_PAGE))
+                .await?;
+            let items = resp
+                .items
+                .remove(path)
+                .unwrap_or(serde_json::Value::Array(Vec::new()));
+            each(
+                serde_json::from_value(items)
+                    .with_context(|| format!("Unexpected response for {}", path))?,
+            );
+            match resp.meta.and_then(|meta| meta.pagination.next_page) {
+                Some(next) if next > page => page = next,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    async fn list<T: DeserializeOwned + Send>(&self, path: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        self.pages(path, |page| items.extend(page)).await?;
+        Ok(items)
+    }
+
+    /// Number of resources behind a list endpoint, without fetching them all.
+    async fn count(&self, path: &str) -> Result<usize> {
+        let resp: Page = self.get(&format!("{}?per_page=1", path)).await?;
+        match resp.meta.and_then(|meta| meta.pagination.total_entries) {
+            Some(total) => Ok(total as usize),
+            None => Ok(self.list::<serde::de::IgnoredAny>(path).await?.len()),
+        }
+    }
+}
+
+impl From<&models::Server> for Server {
+    fn from(server: &models::Server) -> Self {
+        Self {
+            id: server.id.to_string(),
+            name: server.name.clone(),
+            account: String::new(),
+            provider: AuthPlatform::Hetzner,
+            state: match server.status {
+                Status::Running => ServerState::Running,
+                Status::Off => ServerState::Off,
+                Status::Starting => ServerState::Starting,
+                Status::Stopping => ServerState::Stopping,
+                Status::Initializing | Status::Rebuilding | Status::Migrating => {
+                    ServerState::Provisioning
+                }
+                Status::Deleting => ServerState::Deleting,
+                Status::Unknown => ServerState::Unknown,
+            },
+            ipv4: server.public_net.ipv4.as_ref().map(|ipv4| ipv4.ip.clone()),
+            ipv6: server.public_net.ipv6.as_ref().map(|ipv6| ipv6.ip.clone()),
+            created: server.created.clone(),
+            location: server.datacenter.name.clone(),
+            image: server.image.as_ref().and_then(|image| image.name.clone()),
+            labels: server.labels.clone().into_iter().collect(),
+            server_type: server.server_type.name.clone(),
+            disk_size: server.primary_disk_size,
+            traffic: (
+                server.ingoing_traffic.unwrap_or(0) as u64,
+                server.outgoing_traffic.unwrap_or(0) as u64,
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl CloudProvider for Hetzner {
+    fn platform(&self) -> AuthPlatform {
+        AuthPlatform::Hetzner
+    }
+
+    async fn status(&self) -> Result<ProviderStatus> {
+        let mut overview = ProviderStatus::new(self.platform());
+
+        overview.servers = self.count("servers").await?;
+        overview.primary_ips = self.count("primary_ips").await?;
+        overview.firewalls = self.count("firewalls").await?;
+        overview.load_balancers = self.count("load_balancers").await?;
+        overview.quota = self.http.quota();
+
+        Ok(overview)
+    }
+
+    async fn list_server_pages(&self, page: &(dyn Fn(Vec<Server>) + Send + Sync)) -> Result<()> {
+        self.pages("servers", |servers: Vec<models::Server>| {
+            page(servers.iter().map(Server::from).collect())
+        })
+        .await
+    }
+
+    async fn create_server(&self, request: CreateServer) -> Result<(Server, Vec<String>)> {
+        let mut public_net = CreateServerRequestPublicNet {
+            enable_ipv4: Some(request.enable_ipv4),
+            enable_ipv6: Some(request.enable_ipv6),
+            ..Default::default()
+        };
+        if !request.primary_ips.is_empty() {
+            let primary_ips = self.list::<models::PrimaryIp>("primary_ips").await?;
+            for id in &request.primary_ips {
+                let primary_ip = primary_ips
+                    .iter()
+                    .find(|primary_ip| primary_ip.id.to_string() == *id)
+                    .with_context(|| format!("Unknown primary IP {}", id))?;
+                let id = Some(Some(primary_ip.id as i32));
+                match primary_ip.r#type {
+                    IpType::Ipv4 => (public_net.ipv4, public_net.enable_ipv4) = (id, Some(true)),
+                    IpType::Ipv6 => (public_net.ipv6, public_net.enable_ipv6) = (id, Some(true)),
+                }
+            }
+        }
+
+        let ids = |ids: &[String]| {
+            ids.iter()
+                .map(|id| Self::parse_id(id))
+                .collect::<Result<Vec<_>>>()
+        };
+        let request = CreateServerRequest {
+            name: request.name,
+            server_type: request.server_type,
+            start_after_create: Some(true),
+            image: if request.image.is_empty() {
+                IMAGE.to_string()
+            } else {
+                request.image
+            },
+            ssh_keys: Some(request.ssh_keys),
+            // The API rejects a location next to a datacenter
+            location: match request.datacenter {
+                Some(_) => None,
+                None => request.location.or(Some(LOCATION.to_string())),
+            },
+            datacenter: request.datacenter,
+            public_net: Some(Box::new(public_net)),
+            networks: Some(ids(&request.networks)?).filter(|ids| !ids.is_empty()),
+            firewalls: Some(
+                ids(&request.firewalls)?
+                    .into_iter()
+                    .map(|firewall| CreateServerRequestFirewalls { firewall })
+                    .collect::<Vec<_>>(),
+            )
+            .filter(|firewalls| !firewalls.is_empty()),
+            labels: Some(request.labels.into_iter().collect::<HashMap<_, _>>())
+                .filter(|labels| !labels.is_empty()),
+            user_data: request.user_data,
+            ..Default::default()
+        };
+
+        let resp: models::CreateServerResponse = self
+            .send(
+                Method::POST,
+                "servers",
+                Some(serde_json::to_value(request)?),
+            )
+            .await?;
+        // Starting the server comes as a follow-up action
+        let actions = std::iter::once(resp.action.id)
+            .chain(resp.next_actions.iter().map(|action| action.id))
+            .map(|id| id.to_string())
+            .collect();
+        Ok((Server::from(resp.server.as_ref()), actions))
+    }
+
+    async fn delete_server(&self, id: &str) -> Result<Option<String>> {
+        let path = format!("servers/{}", Self::parse_id(id)?);
+        let resp: models::DeleteServerResponse = self.send(Method::DELETE, &path, None).await?;
+        Ok(resp.action.map(|action| action.id.to_string()))
+    }
+
+    async fn get_action(&self, id: &str) -> Result<Option<Action>> {
+        // The generic actions endpoint is gone, every action carton starts is a server action
+        let path = format!("servers/actions/{}", Self::parse_id(id)?);
+        let action = self.get::<models::GetActionResponse>(&path).await?.action;
+        Ok(Some(Action {
+            id: action.id.to_string(),
+            command: action.command,
+            progress: action.progress.clamp(0, 100) as u8,
+            status: match action.status {
+                action::Status::Running => ActionStatus::Running,
+                action::Status::Success => ActionStatus::Success,
+                action::Status::Error => ActionStatus::Error(
+                    action
+                        .error
+                        .map(|err| err.message)
+                        .unwrap_or("unknown error".to_string()),
+                ),
+            },
+        }))
+    }
+
+    async fn power(&self, id: &str, action: PowerAction) -> Result<Option<String>> {
+        let command = match action {
+            PowerAction::PowerOn => "poweron",
+            PowerAction::Shutdown => "shutdown",
+            PowerAction::PowerOff => "poweroff",
+            PowerAction::Reboot => "reboot",
+            PowerAction::Reset => "reset",
+        };
+        let path = format!("servers/{}/actions/{}", Self::parse_id(id)?, command);
+        let resp: models::GetActionResponse = self.send(Method::POST, &path, None).await?;
+        Ok(Some(resp.action.id.to_string()))
+    }
+
+    async fn set_labels(&self, id: &str, labels: BTreeMap<String, String>) -> Result<()> {
+        let path = format!("servers/{}", Self::parse_id(id)?);
+        let request = ReplaceServerRequest {
+            labels: Some(labels.into_iter().collect()),
+            name: None,
+        };
+        self.send::<models::ReplaceServerResponse>(
+            Method::PUT,
+            &path,
+            Some(serde_json::to_value(request)?),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn change_type(&self, id: &str, server_type: &str) -> Result<Option<String>> {
+        // Keeping the disk lets the server be scaled back down later
+        let path = format!("servers/{}/actions/change_type", Self::parse_id(id)?);
+        let request = models::ChangeTypeOfServerRequest {
+            server_type: server_type.to_string(),
+            upgrade_disk: false,
+        };
+        let resp: models::ChangeTypeOfServerResponse = self
+            .send(Method::POST, &path, Some(serde_json::to_value(request)?))
+            .await?;
+        Ok(Some(resp.action.id.to_string()))
+    }
+
+    async fn list_server_types(&self) -> Result<Vec<ServerType>> {
+        Ok(self
+            .list::<models::ServerType>("server_types")
+            .await?
+            .into_iter()
+            .map(|server_type| ServerType {
+                price_monthly: server_type.prices.first().map(|price| {
+                    match price.price_monthly.gross.parse::<f64>() {
+                        Ok(gross) => format!("{:.2} EUR", gross),
+                        Err(_) => price.price_monthly.gross.clone(),
+                    }
+                }),
+                name: server_type.name,
+                description: server_type.description,
+                cores: server_type.cores,
+                memory: server_type.memory,
+                disk: server_type.disk,
+                locations: Vec::new(),
+            })
+            .collect())
+    }
+
+    async fn list_images(&self) -> Result<Vec<Image>> {
+        Ok(self
+            .list::<models::Image>("images")
+            .await?
+            .into_iter()
+            .filter_map(|image| {
+                image.name.map(|name| Image {
+                    name,
+                    description: image.description,
+                })
+            })
+            .collect())
+    }
+
+    async fn list_locations(&self) -> Result<Vec<Location>> {
+        Ok(self
+            .list::<models::Location>("locations")
+            .await?
+            .into_iter()
+            .map(|location| Location {
+                name: location.name,
+                description: location.description,
+            })
+            .collect())
+    }
+
+    async fn list_ssh_keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .list::<models::SshKey>("ssh_keys")
+            .await?
+            .into_iter()
+            .map(|ssh_key| ssh_key.name)
+            .collect())
+    }
+
+    async fn list_datacenters(&self) -> Result<Vec<Resource>> {
+        Ok(self
+            .list::<models::Datacenter>("datacenters")
+            .await?
+            .into_iter()
+            .map(|datacenter| Resource {
+                id: datacenter.name.clone(),
+                name: datacenter.name,
+                description: datacenter.description,
+            })
+            .collect())
+    }
+
+    async fn list_primary_ips(&self) -> Result<Vec<Resource>> {
+        Ok(self
+            .list::<models::PrimaryIp>("primary_ips")
+            .await?
+            .into_iter()
+            .filter(|primary_ip| primary_ip.assignee_id.is_none())
+            .map(|primary_ip| Resource {
+                id: primary_ip.id.to_string(),
+                description: format!("{} ({})", primary_ip.ip, primary_ip.datacenter.name),
+                name: primary_ip.name,
+            })
+            .collect())
+    }
+
+    async fn list_networks(&self) -> Result<Vec<Resource>> {
+        Ok(self
+            .list::<models::Network>("networks")
+            .await?
+            .into_iter()
+            .map(|network| Resource {
+                id: network.id.to_string(),
+                name: network.name,
+                description: network.ip_range,
+            })
+            .collect())
+    }
+
+    async fn list_firewalls(&self) -> Result<Vec<Resource>> {
+        Ok(self
+            .list::<models::Firewall>("firewalls")
+            .await?
+            .into_iter()
+            .map(|firewall| Resource {
+                id: firewall.id.to_string(),
+                name: firewall.name,
+                description: format!("{} rules", firewall.rules.len()),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::testing::serve;
+
+    fn server(id: i64, name: &str, status: &str) -> serde_json::Value {
+        let price = json!({"gross": "4.5100", "net": "3.7900"});
+        json!({
+            "id": id,
+            "name": name,
+            "status": status,
+            "created": "2024-01-01T00:00:00+00:00",
+            "datacenter": {
+                "id": 1,
+                "name": "fsn1-dc14",
+                "description": "Falkenstein 1 virtual DC 14",
+                "location": {
+                    "id": 1,
+                    "name": "fsn1",
+                    "description": "Falkenstein DC Park 1",
+                    "city": "Falkenstein",
+                    "country": "DE",
+                    "latitude": 50.47,
+                    "longitude": 12.37,
+                    "network_zone": "eu-central"
+                },
+                "server_types": {"available": [1], "available_for_migration": [1], "supported": [1]}
+            },
+            "image": {
+                "id": 7,
+                "name": "fedora-41",
+                "description": "Fedora 41",
+                "type": "system",
+                "status": "available",
+                "architecture": "x86",
+                "created": "2024-01-01T00:00:00+00:00",
+                "created_from": null,
+                "bound_to": null,
+                "deleted": null,
+                "deprecated": null,
+                "disk_size": 5,
+                "image_size": null,
+                "labels": {},
+                "os_flavor": "fedora",
+                "os_version": "41",
+                "protection": {"delete": false},
+                "rapid_deploy": true
+            },
+            "server_type": {
+                "id": 1,
+                "name": "cx22",
+                "description": "CX22",
+                "architecture": "x86",
+                "cores": 2,
+                "cpu_type": "shared",
+                "disk": 40,
+                "memory": 4,
+                "deprecated": false,
+                "prices": [{
+                    "location": "fsn1",
+                    "included_traffic": 21990232555520u64,
+                    "price_hourly": price,
+                    "price_monthly": price,
+                    "price_per_tb_traffic": price
+                }],
+                "storage_type": "local"
+            },
+            "public_net": {
+                "firewalls": [],
+                "floating_ips": [],
+                "ipv4": {"id": 3, "ip": "203.0.113.1", "blocked": false, "dns_ptr": "web"},
+                "ipv6": null
+            },
+            "private_net": [],
+            "protection": {"delete": false, "rebuild": false},
+            "labels": {"role": "web"},
+            "locked": false,
+            "rescue_enabled": false,
+            "primary_disk_size": 40,
+            "ingoing_traffic": 1024,
+            "outgoing_traffic": 2048,
+            "included_traffic": 0,
+            "backup_window": null,
+            "iso": null,
+            "load_balancers": [],
+            "placement_group": null,
+            "volumes": []
+        })
+    }
+
+    fn page(servers: Vec<serde_json::Value>, page: i64, next_page: Option<i64>) -> String {
+        json!({
+            "servers": servers,
+            "meta": {"pagination": {
+                "page": page,
+                "per_page": PER_PAGE,
+                "next_page": next_page,
+                "previous_page": null,
+                "last_page": null,
+                "total_entries": null
+            }}
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn server_converts() {
+        let server: models::Server =
+            serde_json::from_value(self::server(42, "web-1", "initializing")).unwrap();
+        let server = Server::from(&server);
+        assert_eq!(server.id, "42");
+        assert_eq!(server.name, "web-1");
+        assert_eq!(server.state, ServerState::Provisioning);
+        assert_eq!(server.location, "fsn1-dc14");
+        assert_eq!(server.image.as_deref(), Some("fedora-41"));
+        assert_eq!(server.server_type, "cx22");
+        assert_eq!(server.ipv4.as_deref(), Some("203.0.113.1"));
+        assert_eq!(server.ipv6, None);
+        assert_eq!(server.labels["role"], "web");
+        assert_eq!(server.disk_size, 40);
+        assert_eq!(server.traffic, (1024, 2048));
+    }
+
+    #[test]
+    fn lists_every_page() {
+        let (endpoint, requests) = serve(vec![
+            page(
+                vec![server(1, "web-1", "running"), server(2, "web-2", "off")],
+                1,
+                Some(2),
+            ),
+            page(vec![server(3, "web-3", "running")], 2, None),
+        ]);
+        let auth = Auth::new(
+            AuthPlatform::Hetzner,
+            "token".to_string(),
+            None,
+            Some(endpoint),
+        );
+        let servers = Runtime::new()
+            .unwrap()
+            .block_on(Hetzner::new(&auth).list_servers())
+            .unwrap();
+        assert_eq!(
+            servers
+                .iter()
+                .map(|server| (server.name.as_str(), server.state))
+                .collect::<Vec<_>>(),
+            [
+                ("web-1", ServerState::Running),
+                ("web-2", ServerState::Off),
+                ("web-3", ServerState::Running)
+            ]
+        );
+
+        let requests = requests.join().unwrap();
+        assert!(requests[0].starts_with("GET /servers?page=1&per_page=50 "));
+        assert!(requests[1].starts_with("GET /servers?page=2&per_page=50 "));
+    }
+}
@@ -0,0 +1,339 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::{Serialize, Serializer};
+
+const ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(500);
+/// Longest carton waits for a retry or for the quota to refill
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The HTTP client every backend shares, so they reuse one connection pool.
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .get_or_init(|| {
+            reqwest::Client::builder()
+                .user_agent(concat!("carton/", env!("CARGO_PKG_VERSION")))
+                .connect_timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
+
+/// Requests an account may still make, as reported by the `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Unix time the quota is full again
+    pub reset: u64,
+}
+
+impl Quota {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+        };
+        let reset = header("RateLimit-Reset").unwrap_or(0);
+        Some(Self {
+            limit: header("RateLimit-Limit")?,
+            remaining: header("RateLimit-Remaining")?,
+            // Hetzner sends a timestamp, the IETF draft seconds from now
+            reset: if reset < 1_000_000_000 {
+                now() + reset
+            } else {
+                reset
+            },
+        })
+    }
+
+    /// How long to hold off before the next request, None while requests are left.
+    fn wait(&self) -> Option<Duration> {
+        if self.remaining > 0 {
+            return None;
+        }
+        let wait = Duration::from_secs(self.reset.saturating_sub(now()).max(1));
+        Some(wait.min(MAX_DELAY))
+    }
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.remaining, self.limit)
+    }
+}
+
+/// Printed as `remaining/limit` so it fits a table cell.
+impl Serialize for Quota {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Exponential backoff with jitter, so clients throttled together spread out.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(1 << attempt).min(MAX_DELAY);
+    let half = delay.as_millis() as u64 / 2;
+    Duration::from_millis(half + fastrand::u64(0..=half))
+}
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_secs(seconds).min(MAX_DELAY))
+}
+
+/// Whether a response status is worth another attempt, see [`Http::send`].
+fn retryable(status: StatusCode, idempotent: bool) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || idempotent
+            && matches!(
+                status,
+                StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            )
+}
+
+/// Sends the requests of one account, retrying throttled and transient failures.
+#[derive(Debug)]
+pub struct Http {
+    client: reqwest::Client,
+    quota: Mutex<Option<Quota>>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            client: client(),
+            quota: Mutex::new(None),
+        }
+    }
+}
+
+impl Http {
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// The quota reported with the last response, if the provider sends one.
+    pub fn quota(&self) -> Option<Quota> {
+        *self.quota.lock().unwrap()
+    }
+
+    /// Sends a request, waiting for the quota to refill when it ran out.
+    ///
+    /// Rejected (429) and unsent requests are always retried, timeouts and
+    /// server errors only when `idempotent`, as the provider may have acted on
+    /// them. The last response is returned as is once the attempts run out.
+    pub async fn send(&self, request: RequestBuilder, idempotent: bool) -> Result<Response> {
+        let (client, request) = request.build_split();
+        let request = request?;
+        let (method, url) = (request.method().clone(), request.url().clone());
+        let mut attempt = 0;
+        loop {
+            if let Some(wait) = self.quota().and_then(|quota| quota.wait()) {
+                tracing::debug!(%url, "Quota drained, waiting {}ms", wait.as_millis());
+                tokio::time::sleep(wait).await;
+            }
+            // Streamed bodies cannot be replayed, those get a single attempt
+            let Some(next) = request.try_clone() else {
+                return Ok(self.observe(client.execute(request).await?));
+            };
+            attempt += 1;
+            let last = attempt == ATTEMPTS;
+
+            let started = Instant::now();
+            let sent = client.execute(next).await;
+            let elapsed_ms = started.elapsed().as_millis() as u64;
+            let delay = match sent {
+                Ok(resp) => {
+                    let resp = self.observe(resp);
+                    let status = resp.status();
+                    if status.is_success() {
+                        tracing::debug!(%method, %url, status = status.as_u16(), elapsed_ms, attempt);
+                    } else {
+                        tracing::warn!(%method, %url, status = status.as_u16(), elapsed_ms, attempt);
+                    }
+                    if !retryable(status, idempotent) || last {
+                        return Ok(resp);
+                    }
+                    retry_after(resp.headers()).or_else(|| {
+                        // A drained quota is already waited for before the next attempt
+                        self.quota()
+                            .and_then(|quota| quota.wait())
+                            .map(|_| Duration::ZERO)
+                    })
+                }
+                Err(err) => {
+                    tracing::warn!(%method, %url, elapsed_ms, attempt, "{}", err);
+                    if !(err.is_connect() || idempotent && err.is_timeout()) || last {
+                        return Err(err.into());
+                    }
+                    None
+                }
+            };
+            tokio::time::sleep(delay.unwrap_or_else(|| backoff(attempt))).await;
+        }
+    }
+
+    fn observe(&self, resp: Response) -> Response {
+        if let Some(quota) = Quota::from_headers(resp.headers()) {
+            *self.quota.lock().unwrap() = Some(quota);
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn quota_reads_timestamp_reset() {
+        let quota = Quota::from_headers(&headers(&[
+            ("RateLimit-Limit", "3600"),
+            ("RateLimit-Remaining", " 3590 "),
+            ("RateLimit-Reset", "1700000000"),
+        ]));
+        assert_eq!(
+            quota,
+            Some(Quota {
+                limit: 3600,
+                remaining: 3590,
+                reset: 1_700_000_000,
+            })
+        );
+    }
+
+    #[test]
+    fn quota_reads_reset_in_seconds() {
+        let before = now();
+        let quota = Quota::from_headers(&headers(&[
+            ("RateLimit-Limit", "100"),
+            ("RateLimit-Remaining", "0"),
+            ("RateLimit-Reset", "10"),
+        ]))
+        .unwrap();
+        assert!((before + 10..=now() + 10).contains(&quota.reset));
+        assert!(quota.wait().is_some_and(|wait| wait <= MAX_DELAY));
+    }
+
+    #[test]
+    fn quota_needs_limit_and_remaining() {
+        assert_eq!(Quota::from_headers(&HeaderMap::new()), None);
+        assert_eq!(
+            Quota::from_headers(&headers(&[("RateLimit-Limit", "100")])),
+            None
+        );
+        assert_eq!(
+            Quota::from_headers(&headers(&[
+                ("RateLimit-Limit", "many"),
+                ("RateLimit-Remaining", "1"),
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn quota_waits_only_when_drained() {
+        let quota = Quota {
+            limit: 10,
+            remaining: 1,
+            reset: now() + 5,
+        };
+        assert_eq!(quota.wait(), None);
+        let drained = Quota {
+            remaining: 0,
+            ..quota
+        };
+        assert!(drained
+            .wait()
+            .is_some_and(|wait| wait >= Duration::from_secs(1)));
+        let overdue = Quota {
+            reset: 0,
+            ..drained
+        };
+        assert_eq!(overdue.wait(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn throttling_is_always_retried() {
+        assert!(retryable(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(retryable(StatusCode::TOO_MANY_REQUESTS, true));
+    }
+
+    #[test]
+    fn server_errors_are_retried_when_idempotent() {
+        for status in [
+            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::GATEWAY_TIMEOUT,
+        ] {
+            assert!(retryable(status, true), "{}", status);
+            assert!(!retryable(status, false), "{}", status);
+        }
+    }
+
+    #[test]
+    fn other_statuses_are_not_retried() {
+        for status in [
+            StatusCode::OK,
+            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
+            StatusCode::CONFLICT,
+            StatusCode::NOT_IMPLEMENTED,
+        ] {
+            assert!(!retryable(status, true), "{}", status);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let wait = |value| retry_after(&headers(&[("Retry-After", value)]));
+        assert_eq!(wait("3"), Some(Duration::from_secs(3)));
+        assert_eq!(wait("3600"), Some(MAX_DELAY));
+        // Dates are left to the backoff
+        assert_eq!(wait("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+
+    #[test]
+    fn backoff_grows_within_bounds() {
+        for attempt in 1..ATTEMPTS {
+            let full = BASE_DELAY.saturating_mul(1 << attempt).min(MAX_DELAY);
+            let delay = backoff(attempt);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+        assert!(backoff(20) <= MAX_DELAY);
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+
+use crate::constants::{Account, AuthPlatform, Defaults, ProviderStatus};
+
+pub mod amazon;
+pub mod google;
+pub mod hetzner;
+pub mod http;
+
+/// Lifecycle state of a server, normalized across providers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ServerState {
+    Running,
+    Off,
+    Starting,
+    Stopping,
+    Rebooting,
+    Provisioning,
+    Deleting,
+    #[default]
+    Unknown,
+}
+
+impl std::fmt::Display for ServerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerState::Running => write!(f, "Running"),
+            ServerState::Off => write!(f, "Off"),
+            ServerState::Starting => write!(f, "Starting"),
+            ServerState::Stopping => write!(f, "Stopping"),
+            ServerState::Rebooting => write!(f, "Rebooting"),
+            ServerState::Provisioning => write!(f, "Provisioning"),
+            ServerState::Deleting => write!(f, "Deleting"),
+            ServerState::Unknown => write!(f, "Unknown"),
+        }
+    }
+}
+
+/// Provider-neutral view of a single server.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Server {
+    pub id: String,
+    pub name: String,
+    pub account: String,
+    pub provider: AuthPlatform,
+    pub state: ServerState,
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    pub created: String,
+    pub location: String,
+    pub image: Option<String>,
+    pub labels: BTreeMap<String, String>,
+    pub server_type: String,
+    pub disk_size: i32,
+    pub traffic: (u64, u64),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CreateServer {
+    pub name: String,
+    pub server_type: String,
+    pub image: String,
+    pub location: Option<String>,
+    /// Pins the server to a datacenter, taking precedence over `location`
+    pub datacenter: Option<String>,
+    pub ssh_keys: Vec<String>,
+    pub enable_ipv4: bool,
+    pub enable_ipv6: bool,
+    /// Ids of existing primary IPs to attach instead of fresh addresses
+    pub primary_ips: Vec<String>,
+    pub networks: Vec<String>,
+    pub firewalls: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    /// Cloud-init user data run on first boot
+    pub user_data: Option<String>,
+}
+
+/// In-place changes to a server, unset fields are kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdateServer {
+    pub server_type: Option<String>,
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAction {
+    PowerOn,
+    /// Graceful ACPI shutdown
+    Shutdown,
+    PowerOff,
+    Reboot,
+    Reset,
+}
+
+impl PowerAction {
+    /// State a server is shown in while the action is running.
+    pub fn pending_state(&self) -> ServerState {
+        match self {
+            PowerAction::PowerOn => ServerState::Starting,
+            PowerAction::Shutdown | PowerAction::PowerOff => ServerState::Stopping,
+            PowerAction::Reboot | PowerAction::Reset => ServerState::Rebooting,
+        }
+    }
+}
+
+impl std::fmt::Display for PowerAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerAction::PowerOn => write!(f, "power on"),
+            PowerAction::Shutdown => write!(f, "shutdown"),
+            PowerAction::PowerOff => write!(f, "power off"),
+            PowerAction::Reboot => write!(f, "reboot"),
+            PowerAction::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServerType {
+    pub name: String,
+    pub description: String,
+    pub cores: i32,
+    pub memory: f64,
+    pub disk: f64,
+    pub price_monthly: Option<String>,
+    /// Locations offering the type, empty when every location does
+    pub locations: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Image {
+    pub name: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Location {
+    pub name: String,
+    pub description: String,
+}
+
+/// Progress of an action a provider runs in the background.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub id: String,
+    pub command: String,
+    /// Percent done
+    pub progress: u8,
+    pub status: ActionStatus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionStatus {
+    Running,
+    Success,
+    Error(String),
+}
+
+impl std::fmt::Display for ActionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionStatus::Running => write!(f, "running"),
+            ActionStatus::Success => write!(f, "success"),
+            ActionStatus::Error(err) => write!(f, "error: {}", err),
+        }
+    }
+}
+
+/// A provider resource a new server can be placed in or attached to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Resource {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+}
+
+/// Operations carton needs from a cloud platform.
+#[async_trait]
+pub trait CloudProvider: std::fmt::Debug + Send + Sync {
+    fn platform(&self) -> AuthPlatform;
+
+    async fn status(&self) -> Result<ProviderStatus>;
+
+    /// Lists the servers a page at a time, handing each page over as it arrives.
+    async fn list_server_pages(&self, page: &(dyn Fn(Vec<Server>) + Send + Sync)) -> Result<()>;
+
+    async fn list_servers(&self) -> Result<Vec<Server>> {
+        let servers = Mutex::new(Vec::new());
+        self.list_server_pages(&|page| servers.lock().unwrap().extend(page))
+            .await?;
+        Ok(servers.into_inner().unwrap())
+    }
+
+    /// Creates a server, returning it and the provider actions to wait on.
+    async fn create_server(&self, request: CreateServer) -> Result<(Server, Vec<String>)>;
+
+    /// Deletes a server, returning the provider action to wait on, if any.
+    async fn delete_server(&self, id: &str) -> Result<Option<String>>;
+
+    /// Waits until an action returned by this provider has finished, used
+    /// when `get_action` cannot report on it.
+    async fn wait_action(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Looks up an action returned by this provider, None when the provider
+    /// cannot report its progress.
+    async fn get_action(&self, _id: &str) -> Result<Option<Action>> {
+        Ok(None)
+    }
+
+    /// Runs a power action, returning the provider action to wait on, if any.
+    async fn power(&self, id: &str, action: PowerAction) -> Result<Option<String>>;
+
+    /// Replaces all labels of a server.
+    async fn set_labels(&self, _id: &str, _labels: BTreeMap<String, String>) -> Result<()> {
+        bail!("Changing labels is not supported on {}", self.platform())
+    }
+
+    /// Changes the type of a stopped server, returning the provider action
+    /// to wait on, if any.
+    async fn change_type(&self, _id: &str, _server_type: &str) -> Result<Option<String>> {
+        bail!(
+            "Changing server types is not supported on {}",
+            self.platform()
+        )
+    }
+
+    async fn list_server_types(&self) -> Result<Vec<ServerType>>;
+
+    async fn list_images(&self) -> Result<Vec<Image>>;
+
+    async fn list_locations(&self) -> Result<Vec<Location>>;
+
+    async fn list_ssh_keys(&self) -> Result<Vec<String>>;
+
+    /// Datacenters within the locations, for providers that expose them.
+    async fn list_datacenters(&self) -> Result<Vec<Resource>> {
+        Ok(Vec::new())
+    }
+
+    /// Unassigned primary IPs that can be attached to a new server.
+    async fn list_primary_ips(&self) -> Result<Vec<Resource>> {
+        Ok(Vec::new())
+    }
+
+    async fn list_networks(&self) -> Result<Vec<Resource>> {
+        Ok(Vec::new())
+    }
+
+    async fn list_firewalls(&self) -> Result<Vec<Resource>> {
+        Ok(Vec::new())
+    }
+}
+
+/// A provider client bound to the named account it was built from.
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub account: String,
+    pub defaults: Defaults,
+    pub provider: Arc<dyn CloudProvider>,
+}
+
+pub fn connect(account: &Account) -> Result<Connection> {
+    let auth = &account.auth;
+    let provider: Arc<dyn CloudProvider> = match auth.auth {
+        AuthPlatform::Amazon => Arc::new(amazon::Amazon::new(auth)?),
+        AuthPlatform::Google => Arc::new(google::Google::new(auth)?),
+        AuthPlatform::Hetzner => Arc::new(hetzner::Hetzner::new(auth)),
+    };
+
+    Ok(Connection {
+        account: account.name.clone(),
+        defaults: account.defaults.clone(),
+        provider,
+    })
+}
@@ -0,0 +1,78 @@
+//! Helpers shared by the unit tests.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Held by tests that read or set environment variables, which the whole
+/// process shares.
+pub fn lock_env() -> MutexGuard<'static, ()> {
+    ENV.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Creates an empty directory under the system temp directory.
+pub fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("carton-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Starts a local HTTP server that answers each request with the next of
+/// `responses`, then stops. Returns its address and a handle yielding the
+/// requests it got, each as its request line and body on separate lines.
+pub fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        let mut responses = responses.into_iter();
+        for stream in listener.incoming() {
+            let mut stream = BufReader::new(stream.unwrap());
+            // Clients keep the connection open for the following requests
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).unwrap();
+                requests.push(format!(
+                    "{}\n{}",
+                    line.trim_end(),
+                    String::from_utf8(body).unwrap()
+                ));
+
+                let response = responses.next().expect("more requests than responses");
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+                if responses.len() == 0 {
+                    return requests;
+                }
+            }
+        }
+        requests
+    });
+    (endpoint, handle)
+}
@@ -0,0 +1,190 @@
+use anyhow::{bail, Result};
+
+use crate::constants::InputId;
+use crate::providers::CreateServer;
+
+/// Checks a server name against the RFC 1123 hostname rules.
+pub fn hostname(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("Name is empty");
+    }
+    if name.len() > 253 {
+        bail!("Name is longer than 253 characters");
+    }
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Every part of the name between dots must be 1 to 63 characters");
+        }
+        if !label
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+        {
+            bail!("Name may only contain letters, digits, dashes and dots");
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            bail!("Name parts must not start or end with a dash");
+        }
+    }
+    Ok(())
+}
+
+pub fn is_hostname(name: &str) -> bool {
+    hostname(name).is_ok()
+}
+
+/// Checks that user data is a YAML document starting with `#cloud-config`.
+pub fn user_data(content: &str) -> Result<()> {
+    if content.lines().next().map(str::trim_end) != Some("#cloud-config") {
+        bail!("User data must start with a #cloud-config line");
+    }
+    match serde_yaml::from_str::<serde_yaml::Value>(content)? {
+        serde_yaml::Value::Mapping(_) | serde_yaml::Value::Null => Ok(()),
+        _ => bail!("User data must be a YAML mapping"),
+    }
+}
+
+/// Finds everything that keeps a create request from being submitted, keyed by
+/// the field to fix. `server_types` and `images` are the values the account
+/// offers, `servers` the names already in the server list.
+pub fn create_server(
+    request: &CreateServer,
+    server_types: &[String],
+    images: &[String],
+    servers: &[String],
+) -> Vec<(InputId, String)> {
+    let mut errors = Vec::new();
+
+    if let Err(err) = hostname(&request.name) {
+        errors.push((InputId::CreateServerName, err.to_string()));
+    } else if servers.contains(&request.name) {
+        errors.push((
+            InputId::CreateServerName,
+            format!("A server named {} already exists", request.name),
+        ));
+    }
+
+    if !server_types.contains(&request.server_type) {
+        errors.push((
+            InputId::CreateServerType,
+            format!("Unknown server type {:?}", request.server_type),
+        ));
+    }
+
+    // No image leaves it to the provider default
+    if !request.image.is_empty() && !images.contains(&request.image) {
+        errors.push((
+            InputId::CreateServerImage,
+            format!("Unknown image {:?}", request.image),
+        ));
+    }
+
+    if let Some(Err(err)) = request.user_data.as_deref().map(user_data) {
+        errors.push((InputId::CreateServerUserData, format!("{:#}", err)));
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_hostnames() {
+        for name in ["web", "web-1", "a.b.c", "1st", &"a".repeat(63)] {
+            assert!(is_hostname(name), "{}", name);
+        }
+        let longest = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(longest.len(), 255);
+        assert!(is_hostname(&longest[..253]));
+    }
+
+    #[test]
+    fn rejects_hostnames() {
+        for name in [
+            "",
+            "-web",
+            "web-",
+            "web_1",
+            "web..1",
+            ".web",
+            "web.",
+            "wéb",
+            "web 1",
+            &"a".repeat(64),
+            &vec!["a".repeat(63); 4].join(".")[..254],
+        ] {
+            assert!(!is_hostname(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn accepts_cloud_config() {
+        assert!(user_data("#cloud-config\n").is_ok());
+        assert!(user_data("#cloud-config  \npackages:\n  - nginx\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_other_user_data() {
+        for content in [
+            "",
+            "#!/bin/sh\necho hi\n",
+            "packages: [nginx]\n#cloud-config\n",
+            "#cloud-config\n- nginx\n",
+            "#cloud-config\npackages: [nginx\n",
+        ] {
+            assert!(user_data(content).is_err(), "{:?}", content);
+        }
+    }
+
+    fn request(name: &str, server_type: &str, image: &str) -> CreateServer {
+        CreateServer {
+            name: name.to_string(),
+            server_type: server_type.to_string(),
+            image: image.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn check(request: &CreateServer) -> Vec<InputId> {
+        let offered = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        create_server(
+            request,
+            &offered(&["cx22"]),
+            &offered(&["fedora-41"]),
+            &offered(&["web-1"]),
+        )
+        .into_iter()
+        .map(|(field, _)| field)
+        .collect()
+    }
+
+    #[test]
+    fn accepts_offered_values() {
+        assert!(check(&request("web-2", "cx22", "fedora-41")).is_empty());
+    }
+
+    #[test]
+    fn accepts_provider_default_image() {
+        assert!(check(&request("web-2", "cx22", "")).is_empty());
+    }
+
+    #[test]
+    fn flags_every_invalid_field() {
+        let mut invalid = request("web-1", "cx99", "arch");
+        invalid.user_data = Some("#!/bin/sh".to_string());
+        assert_eq!(
+            check(&invalid),
+            [
+                InputId::CreateServerName,
+                InputId::CreateServerType,
+                InputId::CreateServerImage,
+                InputId::CreateServerUserData,
+            ]
+        );
+        assert_eq!(
+            check(&request("web_2", "cx22", "")),
+            [InputId::CreateServerName]
+        );
+    }
+}